@@ -2,48 +2,301 @@ extern crate limonlib;
 extern crate arguments;
 
 use std::env;
+use std::io;
+use std::io::Write;
+use std::path;
+use std::process;
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time;
 
-use limonlib::{LimonItem, exec_command, commands};
+use limonlib::{LimonItem, exec_command_cached, error_item, commands, config, state};
+use limonlib::error::CommandError;
 
-struct CommandAndArgs<'a> {
-    command: &'a commands::Command,
-    args: &'a [&'a str],
+struct CommandAndArgs {
+    command: &'static commands::CommandInfo,
+    args: Vec<String>,
+    refresh: Option<time::Duration>,
+    timeout: time::Duration,
+    // set while the command runs, a hung one isn't started again
+    busy: Arc<AtomicBool>,
+}
+
+enum Output {
+    Plain,
+    Pango,
+    I3bar,
+    Waybar,
+    Json,
+    Lemonbar(Option<String>),
+}
+
+fn list_commands() {
+    for info in commands::REGISTRY {
+        let mut usage = info.name.to_string();
+        for arg in info.args {
+            usage.push_str(&format!(" <{}>", arg));
+        }
+
+        println!(
+            "{:<32}{}{}",
+            usage,
+            info.description,
+            if info.needs_root { " (needs root)" } else { "" },
+        );
+    }
+}
+
+const FLAGS: &[&str] = &["list-commands", "list-sensors", "pango", "i3bar", "waybar", "lemonbar", "polybar", "json", "verbose"];
+
+// the arguments crate takes the word after any option as its value, so in
+// `limonbin --pango cpu mem` cpu would be lost; the words after the flags
+// are specs, taken in their places on the command line
+fn specs(words: &[String]) -> Vec<String> {
+    let mut specs = vec![];
+    let mut option: Option<&str> = None;
+
+    for word in words {
+        if let Some(name) = word.strip_prefix("--") {
+            option = Some(name);
+            continue;
+        }
+
+        match option.take() {
+            // the value of an option like --config
+            Some(name) if !FLAGS.contains(&name.trim_start_matches("no-")) => {},
+            // --pango true
+            Some(_) if word.parse::<bool>().is_ok() => {},
+            _ => specs.push(word.clone()),
+        }
+    }
+
+    specs
+}
+
+fn flag(args: &arguments::Arguments, name: &str) -> bool {
+    match args.get::<String>(name) {
+        // a swallowed spec means the flag is set
+        Some(value) => value.parse::<bool>().unwrap_or(true),
+        None => false,
+    }
 }
 
 pub fn main() {
-    let args = env::args();
-    let args = arguments::parse(args).unwrap();
-
-    let wireless_interface = ["wlan0"];
-
-    let cmds = vec![
-        CommandAndArgs{command: &commands::Command::Static(commands::LOADAVG), args: &[]},
-        CommandAndArgs{command: &commands::Command::Static(commands::CPU), args: &[]},
-        CommandAndArgs{command: &commands::Command::Static(commands::MEM), args: &[]},
-        CommandAndArgs{command: &commands::Command::Static(commands::ZRAM), args: &[]},
-        CommandAndArgs{command: &commands::Command::Static(commands::RADEON_VRAM), args: &[]},
-        CommandAndArgs{command: &commands::Command::Static(commands::TRAFFIC), args: &wireless_interface},
-        CommandAndArgs{command: &commands::Command::Static(commands::RADEON_TEMPERATURE), args: &[]},
-        CommandAndArgs{command: &commands::Command::Static(commands::AMD_K10_TEMPERATURE), args: &[]},
-        CommandAndArgs{command: &commands::Command::Static(commands::ATA_HDDTEMP), args: &["/dev/sda"]},
-        CommandAndArgs{command: &commands::Command::Static(commands::ATA_GSENSE_ERROR_RATE), args: &["/dev/sda"]},
-        CommandAndArgs{command: &commands::Command::Static(commands::NETWORK_SPEED), args: &wireless_interface},
-        CommandAndArgs{command: &commands::Command::Static(commands::WIRELESS_SIGNAL), args: &wireless_interface},
-        CommandAndArgs{command: &commands::Command::Static(commands::DISK_IO_SPEED), args: &["sda"]},
-        CommandAndArgs{command: &commands::Command::Static(commands::FS_FREE), args: &["/"]},
-        CommandAndArgs{command: &commands::Command::Static(commands::UPS_VOLTAGE), args: &["nutdev"]},
-        CommandAndArgs{command: &commands::Command::Dynamic(commands::BATTERY), args: &[]},
-    ];
-
-    let results: Vec<LimonItem> = cmds.iter().map(|cmd| exec_command(cmd.command, cmd.args)).collect();
-    let bar = match results.last() {
-        Some(item) => item.bar,
-        _ => None,
+    let words: Vec<String> = env::args().collect();
+    let args = arguments::parse(words.iter().cloned()).unwrap();
+
+    let specs = specs(&words[1..]);
+    let list = flag(&args, "list-commands");
+    let list_sensors = flag(&args, "list-sensors");
+    let pango = flag(&args, "pango");
+    let i3bar = flag(&args, "i3bar");
+    let waybar = flag(&args, "waybar");
+    let lemonbar = flag(&args, "lemonbar");
+    let polybar = flag(&args, "polybar");
+    let json = flag(&args, "json");
+    let verbose = flag(&args, "verbose");
+    let icon_color = args.get::<String>("icon-color");
+
+    let output = if pango {
+        Output::Pango
+    } else if i3bar {
+        Output::I3bar
+    } else if waybar {
+        Output::Waybar
+    } else if json {
+        Output::Json
+    } else if lemonbar || polybar {
+        Output::Lemonbar(icon_color)
+    } else {
+        Output::Plain
     };
 
-    if args.get::<bool>("pango") == Some(true) {
-        limonlib::output_pango(results, 12, "Unifont Bold", 12, bar);
+    if list {
+        list_commands();
+        return;
+    }
+
+    if list_sensors {
+        for line in commands::list_sensors() {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    if let Some(namespace) = args.get::<String>("namespace") {
+        if let Err(e) = state::set_namespace(&namespace) {
+            eprintln!("limonbin: {}", e);
+            process::exit(1);
+        }
+    }
+
+    // the configured list is only used when nothing is asked explicitly
+    let config_path = args.get::<String>("config").map(path::PathBuf::from);
+    let config = if specs.is_empty() {
+        config::load_or_default(config_path.as_deref())
     } else {
-        limonlib::output_plain(results);
+        config::from_specs(&specs)
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("limonbin: {}", e);
+            process::exit(1);
+        }
+    };
+
+    // names are validated while parsing, so the lookup can't fail here
+    let cmds: Vec<CommandAndArgs> = config.commands.into_iter().map(|entry| CommandAndArgs {
+        command: commands::find(&entry.name).expect("Validated command not found"),
+        refresh: entry.refresh(),
+        timeout: entry.timeout(),
+        busy: Arc::new(AtomicBool::new(false)),
+        args: entry.args,
+    }).collect();
+
+    // a bare --interval reads as "true"
+    let interval = match args.get::<String>("interval").map(|value| value.parse::<f64>().map_err(|_| value)) {
+        Some(Ok(interval)) => match config::seconds(interval) {
+            Ok(interval) => Some(interval),
+            Err(e) => {
+                eprintln!("limonbin: the interval {}", e);
+                process::exit(1);
+            },
+        },
+        Some(Err(value)) if value == "true" => {
+            eprintln!("limonbin: the interval needs a number of seconds");
+            process::exit(1);
+        },
+        Some(Err(value)) => {
+            eprintln!("limonbin: the interval must be a number of seconds, got \"{}\"", value);
+            process::exit(1);
+        },
+        None => None,
+    };
+
+    if let Output::I3bar = output {
+        limonlib::output_i3bar_header();
+    }
+
+    match interval {
+        Some(interval) => {
+            // deltas are computed against the previous tick, no files needed
+            state::keep_in_memory();
+
+            loop {
+                let started = time::Instant::now();
+
+                commands::start_tick();
+                print_once(&cmds, &output, verbose);
+
+                if let Some(rest) = interval.checked_sub(started.elapsed()) {
+                    thread::sleep(rest);
+                }
+            }
+        },
+        None => print_once(&cmds, &output, verbose),
     }
 }
+
+fn failed_item(cmd: &CommandAndArgs, message: String) -> LimonItem {
+    let args: Vec<&str> = cmd.args.iter().map(String::as_str).collect();
+    error_item(cmd.command, &args, CommandError::Transient(message))
+}
+
+// clears the busy flag of a command however its thread ends, panics included
+struct BusyGuard(Arc<AtomicBool>);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+// every command runs in its own thread, so a slow one doesn't hold the others
+fn exec_commands(cmds: &[CommandAndArgs]) -> Vec<LimonItem> {
+    let started = time::Instant::now();
+    let (sender, receiver) = mpsc::channel();
+    let mut results: Vec<Option<LimonItem>> = cmds.iter().map(|_| None).collect();
+
+    for (i, cmd) in cmds.iter().enumerate() {
+        if cmd.busy.swap(true, Ordering::SeqCst) {
+            results[i] = Some(failed_item(cmd, "still running since the previous tick".to_string()));
+            continue;
+        }
+
+        let sender = sender.clone();
+        let command = cmd.command;
+        let args = cmd.args.clone();
+        let refresh = cmd.refresh;
+        let busy = BusyGuard(cmd.busy.clone());
+        thread::spawn(move || {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let item = exec_command_cached(command, &args, refresh);
+            drop(busy);
+            // nobody listens if the command took too long
+            let _ = sender.send((i, item));
+        });
+    }
+    drop(sender);
+
+    loop {
+        let deadline = cmds.iter().zip(&results)
+            .filter(|(_, result)| result.is_none())
+            .map(|(cmd, _)| cmd.timeout)
+            .min();
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => break,
+        };
+
+        match receiver.recv_timeout(deadline.saturating_sub(started.elapsed())) {
+            Ok((i, item)) => results[i] = Some(item),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let elapsed = started.elapsed();
+                for (cmd, result) in cmds.iter().zip(results.iter_mut()) {
+                    if result.is_none() && cmd.timeout <= elapsed {
+                        *result = Some(failed_item(cmd, format!("timed out after {:?}", cmd.timeout)));
+                    }
+                }
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    // a thread that panicked never sends its result
+    cmds.iter().zip(results).map(|(cmd, result)| {
+        result.unwrap_or_else(|| failed_item(cmd, "crashed".to_string()))
+    }).collect()
+}
+
+fn print_once(cmds: &[CommandAndArgs], output: &Output, verbose: bool) {
+    let results = exec_commands(cmds);
+
+    if verbose {
+        for (cmd, item) in cmds.iter().zip(&results) {
+            if let Some(error) = &item.error {
+                if cmd.args.is_empty() {
+                    eprintln!("limonbin: {}: {}", cmd.command.name, error);
+                } else {
+                    eprintln!("limonbin: {}({}): {}", cmd.command.name, cmd.args.join(":"), error);
+                }
+            }
+        }
+    }
+
+    let bar = limonlib::panel_bar(&results);
+
+    match output {
+        Output::Pango => { limonlib::output_pango(results, 12, "Unifont Bold", 12, bar); },
+        Output::I3bar => { limonlib::output_i3bar(results, 6); },
+        Output::Waybar => { limonlib::output_waybar(results, bar); },
+        Output::Json => { limonlib::output_json(results); },
+        Output::Lemonbar(icon_color) => { limonlib::output_lemonbar(results, 2, icon_color.as_deref(), 6); },
+        Output::Plain => { limonlib::output_plain(results); },
+    }
+
+    // stdout is a pipe to the bar in the resident mode
+    let _ = io::stdout().flush();
+}
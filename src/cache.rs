@@ -0,0 +1,97 @@
+extern crate serde;
+extern crate serde_json;
+
+use std::time;
+
+use serde::{Serialize, Deserialize};
+
+use super::error::CommandError;
+use super::measurement::Measurement;
+use super::state;
+
+const KEY_PREFIX: &str = "cache/";
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    // milliseconds since the epoch, the cache has to survive between runs
+    taken: u64,
+    measurement: Measurement,
+}
+
+fn now() -> u64 {
+    time::SystemTime::now().duration_since(time::UNIX_EPOCH).map(|now| now.as_millis() as u64).unwrap_or(0)
+}
+
+// a clock set back makes the entry stale rather than fresh forever
+fn is_fresh(taken: u64, now: u64, refresh: time::Duration) -> bool {
+    now >= taken && ((now - taken) as u128) < refresh.as_millis()
+}
+
+pub fn key(name: &str, instance: &str) -> String {
+    KEY_PREFIX.to_owned() + &state::key(name, instance)
+}
+
+// reuses the last good measurement until it's older than the refresh interval
+pub fn measure<F>(key: &str, refresh: time::Duration, measure: F) -> Result<Measurement, CommandError>
+    where F: FnOnce() -> Result<Measurement, CommandError>
+{
+    let now = now();
+
+    if let Some(entry) = state::load(key).and_then(|entry| serde_json::from_str::<Entry>(&entry).ok()) {
+        if is_fresh(entry.taken, now, refresh) {
+            return Ok(entry.measurement);
+        }
+    }
+
+    let measurement = measure()?;
+
+    // a lost cache entry only costs one more measurement
+    if let Ok(entry) = serde_json::to_string(&Entry { taken: now, measurement: measurement.clone() }) {
+        let _ = state::save(key, &entry);
+    }
+
+    Ok(measurement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::measurement::Unit;
+
+    #[test]
+    fn fresh_within_refresh() {
+        assert!(is_fresh(1000, 5000, time::Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn stale_after_refresh() {
+        assert!(!is_fresh(1000, 6000, time::Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn stale_from_future() {
+        assert!(!is_fresh(6000, 1000, time::Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn key_with_instance() {
+        assert_eq!(key("loadavg", ""), "cache/loadavg");
+        assert_eq!(key("ata_hddtemp", "/dev/sda"), "cache/ata_hddtemp//dev/sda");
+    }
+
+    #[test]
+    fn entry_roundtrip() {
+        let entry = Entry { taken: 42, measurement: Measurement::new(&[("temperature", 38.0, Unit::Celsius)]) };
+        let entry: Entry = serde_json::from_str(&serde_json::to_string(&entry).unwrap()).unwrap();
+        assert_eq!(entry.taken, 42);
+        assert_eq!(entry.measurement.get("temperature"), Some(38.0));
+    }
+
+    #[test]
+    fn entry_roundtrip_nan() {
+        let entry = Entry { taken: 42, measurement: Measurement::new(&[("rx", f64::NAN, Unit::BytesPerSecond), ("tx", 512.0, Unit::BytesPerSecond)]) };
+        let entry: Entry = serde_json::from_str(&serde_json::to_string(&entry).unwrap()).unwrap();
+        assert!(entry.measurement.get("rx").unwrap().is_nan());
+        assert_eq!(entry.measurement.get("tx"), Some(512.0));
+    }
+}
@@ -2,37 +2,37 @@ extern crate procfs;
 extern crate lazy_static;
 extern crate linereader;
 extern crate regex;
-extern crate rust_decimal;
-extern crate once_cell;
 extern crate sensors;
 extern crate hdd;
 extern crate itertools;
 extern crate libc;
 extern crate battery;
 
-use std::env;
 use std::fs;
-use std::path;
 use std::io;
-use std::io::Write;
+use std::path;
 use std::str::FromStr;
-use std::convert::TryInto;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::mem;
 use std::ffi::CString;
+use std::sync::{Arc, Mutex};
 
+use super::delta;
+use super::state;
 use super::utils::trim_trailing_newline;
+use super::error::CommandError;
+use super::measurement::{Measurement, Severity, Unit};
+use super::format::{format_value, format_single, format_slash_pair, format_colon_pair, format_two_amounts, format_sparkline};
 
 use lazy_static::lazy_static;
 use linereader::LineReader;
 use regex::Regex;
-use rust_decimal::Decimal;
-use once_cell::sync::OnceCell;
-use sensors::Sensors;
+use sensors::{Sensors, Feature, FeatureType, SubfeatureType};
 use hdd::ata::ATADevice;
 use hdd::scsi::SCSIDevice;
 use hdd::ata::misc::Misc;
 use hdd::ata::data::attr::raw::Raw as HDDRaw;
+use itertools::Itertools;
 use itertools::free::join;
 use rups::blocking::Connection;
 use rups::ConfigBuilder;
@@ -42,61 +42,26 @@ use battery::units::energy::watt_hour;
 pub struct StaticIconCommand
 {
     pub icon: char,
-    pub call: fn(&[&str]) -> Option<String>,
+    pub measure: fn(&[&str]) -> Result<Measurement, CommandError>,
+    pub format: fn(&Measurement, &[&str]) -> String,
     pub pre_spaces: usize,
     pub post_spaces: usize,
 }
 
-pub struct DynamicIconCommandOutput
+pub struct DynamicIconCommand
 {
-    pub icon: char,
-    pub text: String,
-    pub bar: Option<u8>,
+    pub icon: fn(&Measurement) -> char,
+    pub measure: fn(&[&str]) -> Result<Measurement, CommandError>,
+    pub format: fn(&Measurement, &[&str]) -> String,
     pub pre_spaces: usize,
     pub post_spaces: usize,
 }
 
-pub struct DynamicIconCommand
-{
-    pub call: fn(&[&str]) -> Option<DynamicIconCommandOutput>,
-}
-
 pub enum Command {
     Static(StaticIconCommand),
     Dynamic(DynamicIconCommand),
 }
 
-const FILE_PREFIX: &str = ".limon-";
-macro_rules! TEMPERATURE_FORMAT { () => { "{:+.1}°C" }; }
-
-// prefer /run, but /tmp is fine too
-lazy_static! {
-    static ref TEMP_DIR: path::PathBuf = {
-        Some(match env::var("XDG_RUNTIME_DIR") {
-            Ok(dir) => path::PathBuf::from(dir),
-            Err(_) => env::temp_dir(),
-        }).expect("")
-    };
-}
-
-fn persist_state(name: &str, save: &str) -> io::Result<String> {
-    let mut file_path_buf = TEMP_DIR.clone();
-    file_path_buf.push(FILE_PREFIX.to_owned() + name);
-    let file_path = file_path_buf.as_path();
-
-    let mut prev_state = String::new();
-
-    // nothing bad if the file doesn't exist, just return an empty state
-    if let Ok(contents) = fs::read_to_string(file_path) {
-        prev_state = contents;
-    }
-
-    let new_file = fs::File::create(file_path)?;
-    write!(&new_file, "{}", save)?;
-
-    Ok(prev_state)
-}
-
 fn read_u32_from_file(filename: &str) -> io::Result<u32> {
     let mut contents = fs::read_to_string(filename)?;
 
@@ -108,170 +73,364 @@ fn read_u32_from_file(filename: &str) -> io::Result<u32> {
     }
 }
 
-fn cpu_freq_icon(cpu_no: &str) -> io::Result<String> {
+fn arg<'a>(args: &[&'a str], n: usize, name: &str) -> Result<&'a str, CommandError> {
+    match args.get(n) {
+        Some(arg) => Ok(arg),
+        None => Err(CommandError::Parse(format!("missing {} argument", name))),
+    }
+}
+
+// position of the current frequency between min and max, in percents
+fn cpu_freq_level(cpu_no: &str) -> io::Result<f64> {
     let path_base = "/sys/devices/system/cpu/cpufreq/policy".to_string() + cpu_no;
 
     let min_freq = read_u32_from_file(&(path_base.to_string() + "/cpuinfo_min_freq"))?;
     let max_freq = read_u32_from_file(&(path_base.to_string() + "/cpuinfo_max_freq"))?;
     let cur_freq = read_u32_from_file(&(path_base + "/scaling_cur_freq"))?;
 
-    let boundary1 = min_freq + (max_freq - min_freq) / 3;
-    let boundary2 = min_freq + (max_freq - min_freq) * 2 / 3;
-
-    if cur_freq < boundary1 {
-        Ok("·".to_string())
-    } else if cur_freq > boundary2 {
-        Ok("⁝".to_string())
-    } else {
-        Ok("⁚".to_string())
+    if max_freq <= min_freq {
+        return Ok(0.0);
     }
+
+    Ok(100.0 * (cur_freq as f64 - min_freq as f64) / (max_freq - min_freq) as f64)
 }
 
-fn format_amount(mantissa: Decimal) -> String {
-    if mantissa < Decimal::ONE && mantissa != Decimal::ZERO {
-        format!("{:.2}", mantissa)
-    } else if mantissa < Decimal::ONE_HUNDRED && mantissa.fract() != Decimal::ZERO {
-        format!("{:.1}", mantissa)
+fn freq_level_icon(level: f64) -> &'static str {
+    if level < 100.0 / 3.0 {
+        "·"
+    } else if level > 200.0 / 3.0 {
+        "⁝"
     } else {
-        format!("{:.0}", mantissa)
+        "⁚"
     }
 }
 
+#[derive(Clone)]
+struct Traffic {
+    rx: u64,
+    tx: u64,
+}
+type MaybeTraffic = Result<Traffic, CommandError>;
+
+// one read per interface per tick, shared by TRAFFIC and NETWORK_SPEED
 lazy_static! {
-    static ref BYTE_SUFFIX_MAP: BTreeMap<u64, &'static str> = {
-        let mut map = BTreeMap::new();
-        map.insert(10 * (1 << 10), "K");
-        map.insert(10 * (1 << 20), "M");
-        map.insert(10 * (1 << 30), "G");
-        map.insert(10 * (1 << 40), "T");
-        map.insert(10 * (1 << 50), "P");
-        map
-    };
+    static ref RX_TX: Mutex<HashMap<String, MaybeTraffic>> = Mutex::new(HashMap::new());
+}
+
+// shown instead of the traffic when the interface is gone, reference to
+// http://web.archive.org/web/20130430040505/http://promodj.com/cybersatan/tracks/4073655/ZB_CyberSatan_TDPLM_Akti_2_3_Otkrovenie_i_Problemi_s_Setyu :)
+const DISCONNECTED: &str = "Дисконнект, б**";
+
+const NET_DIR: &str = "/sys/class/net";
+// the pseudo-interface summing up all the others but loopback
+const ALL_INTERFACES: &str = "all";
+const ARPHRD_LOOPBACK: &str = "772";
+
+fn fetch_traffic(net_dir: &path::Path, iface: &str) -> MaybeTraffic {
+    let path_base = net_dir.join(iface).join("statistics");
+
+    let mut rx_string = fs::read_to_string(path_base.join("rx_bytes"))?;
+    trim_trailing_newline(&mut rx_string);
+    let mut tx_string = fs::read_to_string(path_base.join("tx_bytes"))?;
+    trim_trailing_newline(&mut tx_string);
+
+    Ok(Traffic {
+        rx: u64::from_str(&rx_string)?,
+        tx: u64::from_str(&tx_string)?,
+    })
+}
+
+fn is_loopback(net_dir: &path::Path, iface: &str) -> bool {
+    match fs::read_to_string(net_dir.join(iface).join("type")) {
+        Ok(iface_type) => iface_type.trim_end() == ARPHRD_LOOPBACK,
+        Err(_) => false,
+    }
 }
-fn format_two_amounts(a1: u64, a2: u64, separator: &str, bytes: bool) -> String {
-    let bearer = std::cmp::max(a1, a2);
-
-    let mut bearer_ceil = &10;
-    for ceil in BYTE_SUFFIX_MAP.keys() {
-        // stop when this ceil would lead to a leading zero or too many digits
-        if bearer < *ceil {
-            break;
+
+fn fetch_all_traffic(net_dir: &path::Path) -> MaybeTraffic {
+    let mut total = Traffic { rx: 0, tx: 0 };
+
+    for entry in fs::read_dir(net_dir)? {
+        let iface = entry?.file_name();
+        let iface = iface.to_string_lossy();
+        if is_loopback(net_dir, &iface) {
+            continue;
+        }
+
+        match fetch_traffic(net_dir, &iface) {
+            Ok(traffic) => {
+                total.rx = total.rx.wrapping_add(traffic.rx);
+                total.tx = total.tx.wrapping_add(traffic.tx);
+            },
+            // went away while listing
+            Err(CommandError::NotAvailable(_)) => {},
+            Err(e) => return Err(e),
         }
-        bearer_ceil = ceil;
     }
 
-    let bearer_suffix = match BYTE_SUFFIX_MAP.get(bearer_ceil) {
-        Some(suffix) => suffix,
-        None => if bytes { "B" } else { "" }
-    };
+    Ok(total)
+}
+
+fn fetch_traffic_cached(iface: &str) -> MaybeTraffic {
+    let mut rx_tx = RX_TX.lock().unwrap();
+
+    match rx_tx.get(iface) {
+        // return the cached result
+        Some(Ok(traffic)) => Ok(traffic.clone()),
+        // wasn't fetched yet or fetched with an error, try again
+        _ => {
+            let net_dir = path::Path::new(NET_DIR);
+            let traffic = if iface == ALL_INTERFACES {
+                fetch_all_traffic(net_dir)
+            } else {
+                fetch_traffic(net_dir, iface)
+            };
 
-    let bearer_ceil = Decimal::from(bearer_ceil / 10);
+            rx_tx.insert(iface.to_string(), traffic.clone());
 
-    format_amount(Decimal::from(a1) / bearer_ceil) + separator +
-        &format_amount(Decimal::from(a2) / bearer_ceil) + bearer_suffix
+            traffic
+        },
+    }
 }
 
-#[derive(Clone)]
-struct Traffic {
-    rx: u64,
-    tx: u64,
-    rx_string: String,
-    tx_string: String,
-    iface: String,
+const ROUTE_FILE: &str = "/proc/net/route";
+// the pseudo-interface resolved to the one with the default route
+const AUTO_INTERFACE: &str = "auto";
+const RTF_UP: u16 = 0x1;
+
+// the default route with the lowest metric, "Iface Destination Gateway Flags RefCnt Use Metric Mask ..."
+fn default_route_iface(route: &str) -> Option<String> {
+    route.lines().skip(1).filter_map(|line| {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        if columns.len() < 8 || columns[1] != "00000000" || columns[7] != "00000000" {
+            return None;
+        }
+
+        let flags = u16::from_str_radix(columns[3], 16).ok()?;
+        let metric = u32::from_str(columns[6]).ok()?;
+        if flags & RTF_UP == 0 {
+            return None;
+        }
+
+        Some((metric, columns[0]))
+    }).min_by_key(|(metric, _)| *metric).map(|(_, iface)| iface.to_string())
 }
-type MaybeTraffic = Result<Traffic, String>;
-static RX_TX: OnceCell<MaybeTraffic> = OnceCell::new();
-
-fn fetch_traffic(iface: &str) -> MaybeTraffic {
-    let path_base = "/sys/class/net/".to_string() + iface + "/statistics/";
-
-    // read rx
-    match fs::read_to_string(path_base.to_owned() + "rx_bytes") {
-        Ok(mut rx_string) => {
-            trim_trailing_newline(&mut rx_string);
-            // read tx
-            if let Ok(mut tx_string) = fs::read_to_string(path_base + "tx_bytes") {
-                trim_trailing_newline(&mut tx_string);
-
-                if let Ok(result) = || -> Result<Traffic, std::num::ParseIntError> {
-                    let rx = u64::from_str(&rx_string)?;
-                    let tx = u64::from_str(&tx_string)?;
-
-                    Ok(Traffic {
-                        rx: rx,
-                        tx: tx,
-                        rx_string: rx_string,
-                        tx_string: tx_string,
-                        iface: iface.to_string(),
-                    })
-                }() {
-                    return Ok(result);
-                }
-            }
-        },
-        Err(_) => {
-            // reference to http://web.archive.org/web/20130430040505/http://promodj.com/cybersatan/tracks/4073655/ZB_CyberSatan_TDPLM_Akti_2_3_Otkrovenie_i_Problemi_s_Setyu :)
-            return Err("Дисконнект, б**".to_string())
+
+// no default route, e.g. a link without DHCP yet
+fn first_up_iface(net_dir: &path::Path) -> Option<String> {
+    let mut ifaces: Vec<String> = fs::read_dir(net_dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|iface| !is_loopback(net_dir, iface))
+        .filter(|iface| match fs::read_to_string(net_dir.join(iface).join("operstate")) {
+            Ok(operstate) => operstate.trim_end() == "up",
+            Err(_) => false,
+        })
+        .collect();
+
+    ifaces.sort();
+    ifaces.into_iter().next()
+}
+
+fn resolve_iface(iface: &str) -> Result<String, CommandError> {
+    if iface != AUTO_INTERFACE {
+        return Ok(iface.to_string());
+    }
+
+    fs::read_to_string(ROUTE_FILE).ok().as_deref().and_then(default_route_iface)
+        .or_else(|| first_up_iface(path::Path::new(NET_DIR)))
+        .ok_or_else(|| CommandError::NotAvailable("no interface is up".to_string()))
+}
+
+// shows which interface "auto" turned out to be
+fn label_auto(iface: &str, resolved: &str, measurement: Measurement) -> Measurement {
+    if iface == AUTO_INTERFACE {
+        measurement.with_label(resolved)
+    } else {
+        measurement
+    }
+}
+
+// libsensors reads the values on every get_value, only the chip list is
+// collected once
+lazy_static! {
+    static ref SENSORS: Sensors = Sensors::new();
+}
+// libsensors matches the chip names with wildcards itself, e.g. coretemp-*;
+// the feature is matched by its label or name, the first one of the type if there's none
+fn find_feature(chip_glob: &str, feature_label: Option<&str>, feature_type: FeatureType) -> Result<Option<(String, Feature)>, CommandError> {
+    let chips = SENSORS.detected_chips(chip_glob)
+        .map_err(|e| CommandError::Parse(format!("bad chip name {}: {}", chip_glob, e)))?;
+
+    for chip in chips {
+        let chip_name = chip.get_name()?;
+        let feature = chip.into_iter().find(|feat| *feat.feature_type() == feature_type && match feature_label {
+            Some(label) => feat.name() == label || feat.get_label().map(|l| l == label).unwrap_or(false),
+            None => true,
+        });
+
+        if let Some(feature) = feature {
+            return Ok(Some((chip_name, feature)));
         }
     }
 
-    Err("".to_string())
+    Ok(None)
 }
 
-fn update_traffic(iface: &str) -> MaybeTraffic {
-    let traffic = fetch_traffic(iface);
+// chip and feature pairs tried in order for the aliases
+const CPU_TEMPERATURE_PROBES: &[(&str, Option<&str>)] = &[
+    ("coretemp-*", Some("Package id 0")),
+    ("k10temp-*", Some("Tctl")),
+    ("k10temp-*", Some("temp1")),
+    ("zenpower-*", Some("Tdie")),
+    ("cpu_thermal-*", None),
+];
+const GPU_TEMPERATURE_PROBES: &[(&str, Option<&str>)] = &[
+    ("amdgpu-*", Some("edge")),
+    ("radeon-*", Some("temp1")),
+    ("nouveau-*", Some("temp1")),
+];
 
-    // Err means that the cell isn't empty, ignore it
-    match RX_TX.set(traffic.clone()) {
-        Ok(_) => {},
-        Err(_) => {}
+fn temperature_probes<'a>(chip: &'a str, feature: Option<&'a str>) -> Result<Vec<(&'a str, Option<&'a str>)>, CommandError> {
+    match (chip, feature) {
+        ("cpu", None) => Ok(CPU_TEMPERATURE_PROBES.to_vec()),
+        ("gpu", None) => Ok(GPU_TEMPERATURE_PROBES.to_vec()),
+        // the aliases pick the feature themselves
+        ("cpu", Some(feature)) | ("gpu", Some(feature)) =>
+            Err(CommandError::Parse(format!("{} takes no feature, got \"{}\"", chip, feature))),
+        (chip, feature) => Ok(vec![(chip, feature)]),
     }
+}
 
-    traffic
+// what to read from a feature of the given type
+struct SensorKind {
+    name: &'static str,
+    feature_type: FeatureType,
+    // the first one the chip has is used, e.g. amdgpu has only the average power
+    inputs: &'static [SubfeatureType],
+    min: Option<SubfeatureType>,
+    max: Option<SubfeatureType>,
+    alarms: &'static [SubfeatureType],
+    unit: Unit,
 }
 
-fn fetch_traffic_cached(iface: &str) -> MaybeTraffic {
-    match RX_TX.get() {
-        Some(rx_tx) => {
-            match (*rx_tx).clone() {
-                Ok(rx_tx) => {
-                    // return the cached result
-                    if rx_tx.iface == iface {
-                        Ok(rx_tx)
-                    // fetch for another interface, don't touch the cache
-                    } else {
-                        fetch_traffic(iface)
-                    }
-                },
-                // fetched with an error first time, try again
-                Err(_) => {
-                    update_traffic(iface)
-                }
-            }
-        },
-        // wasn't fetched yet, fetch and cache
-        None => {
-            update_traffic(iface)
+const TEMPERATURE_SENSOR: SensorKind = SensorKind {
+    name: "temperature",
+    feature_type: FeatureType::SENSORS_FEATURE_TEMP,
+    inputs: &[SubfeatureType::SENSORS_SUBFEATURE_TEMP_INPUT],
+    min: None,
+    max: Some(SubfeatureType::SENSORS_SUBFEATURE_TEMP_MAX),
+    alarms: &[
+        SubfeatureType::SENSORS_SUBFEATURE_TEMP_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_TEMP_MAX_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_TEMP_CRIT_ALARM,
+    ],
+    unit: Unit::Celsius,
+};
+const FAN_SENSOR: SensorKind = SensorKind {
+    name: "fan",
+    feature_type: FeatureType::SENSORS_FEATURE_FAN,
+    inputs: &[SubfeatureType::SENSORS_SUBFEATURE_FAN_INPUT],
+    min: Some(SubfeatureType::SENSORS_SUBFEATURE_FAN_MIN),
+    max: Some(SubfeatureType::SENSORS_SUBFEATURE_FAN_MAX),
+    alarms: &[
+        SubfeatureType::SENSORS_SUBFEATURE_FAN_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_FAN_FAULT,
+        SubfeatureType::SENSORS_SUBFEATURE_FAN_MIN_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_FAN_MAX_ALARM,
+    ],
+    unit: Unit::Rpm,
+};
+const VOLTAGE_SENSOR: SensorKind = SensorKind {
+    name: "voltage",
+    feature_type: FeatureType::SENSORS_FEATURE_IN,
+    inputs: &[SubfeatureType::SENSORS_SUBFEATURE_IN_INPUT],
+    min: Some(SubfeatureType::SENSORS_SUBFEATURE_IN_MIN),
+    max: Some(SubfeatureType::SENSORS_SUBFEATURE_IN_MAX),
+    alarms: &[
+        SubfeatureType::SENSORS_SUBFEATURE_IN_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_IN_MIN_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_IN_MAX_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_IN_LCRIT_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_IN_CRIT_ALARM,
+    ],
+    unit: Unit::Volts,
+};
+const POWER_SENSOR: SensorKind = SensorKind {
+    name: "power",
+    feature_type: FeatureType::SENSORS_FEATURE_POWER,
+    inputs: &[SubfeatureType::SENSORS_SUBFEATURE_POWER_INPUT, SubfeatureType::SENSORS_SUBFEATURE_POWER_AVERAGE],
+    min: None,
+    max: Some(SubfeatureType::SENSORS_SUBFEATURE_POWER_MAX),
+    alarms: &[
+        SubfeatureType::SENSORS_SUBFEATURE_POWER_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_POWER_CAP_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_POWER_MAX_ALARM,
+        SubfeatureType::SENSORS_SUBFEATURE_POWER_CRIT_ALARM,
+    ],
+    unit: Unit::Watts,
+};
+
+// unset limits are zeroes, a raised alarm is non-zero
+fn sensor_severity(input: f64, min: Option<f64>, max: Option<f64>, alarms: &[f64]) -> Severity {
+    let below_min = matches!(min, Some(min) if min > 0.0 && input < min);
+    let above_max = matches!(max, Some(max) if max > 0.0 && input > max);
+
+    if below_min || above_max || alarms.iter().any(|alarm| *alarm != 0.0) {
+        Severity::Warning
+    } else {
+        Severity::Normal
+    }
+}
+
+fn get_chip_value(kind: &SensorKind, probes: &[(&str, Option<&str>)]) -> Result<Measurement, CommandError> {
+    for (chip_glob, feature_label) in probes {
+        if let Some((chip_name, feat)) = find_feature(chip_glob, *feature_label, kind.feature_type)? {
+            let input = kind.inputs.iter().find_map(|input| feat.get_subfeature(*input))
+                .ok_or_else(|| CommandError::NotAvailable(format!("no input for {} on {}", feat.name(), chip_name)))?;
+            let input = input.get_value()?;
+
+            // the limits are optional, a chip that can't read them still has the value
+            let read = |subfeature: SubfeatureType| feat.get_subfeature(subfeature).and_then(|subfeat| subfeat.get_value().ok());
+            let alarms: Vec<f64> = kind.alarms.iter().filter_map(|alarm| read(*alarm)).collect();
+            let severity = sensor_severity(input, kind.min.and_then(read), kind.max.and_then(read), &alarms);
+
+            return Ok(Measurement::new(&[(kind.name, input, kind.unit)]).with_severity(severity));
         }
     }
+
+    Err(CommandError::NotAvailable(format!("no {} sensor on {}", kind.name, join(probes.iter().map(|(chip, _)| chip).dedup(), ", "))))
 }
 
-lazy_static! {
-    static ref SENSORS: Sensors = Sensors::new();
+fn get_chip_temperature(probes: &[(&str, Option<&str>)]) -> Result<Measurement, CommandError> {
+    get_chip_value(&TEMPERATURE_SENSOR, probes)
 }
-fn get_chip_temperature(chip_name: &str, temperature_name: &str) -> Option<String> {
-    let chip_name = chip_name.to_string();
-    if let Some(chip) = (*SENSORS).into_iter().find(|chip| chip.get_name().expect("") == chip_name) {
-        if let Some(feat) = chip.into_iter().find(|feat| feat.name() == temperature_name) {
-            if let Some(subfeat) = feat.get_subfeature(sensors::SubfeatureType::SENSORS_SUBFEATURE_TEMP_INPUT) {
-                if let Ok(value) = subfeat.get_value() {
-                    return Some(format!(TEMPERATURE_FORMAT!(), value));
-                }
-            }
+
+// chip and optional feature, like the temperature but without the aliases
+fn measure_sensor(kind: &SensorKind, args: &[&str]) -> Result<Measurement, CommandError> {
+    let chip = arg(args, 0, "chip")?;
+    get_chip_value(kind, &[(chip, args.get(1).copied())])
+}
+
+// every chip with its features and their subfeatures, like `sensors -u`
+pub fn list_sensors() -> Vec<String> {
+    let mut lines = vec![];
+
+    for chip in *SENSORS {
+        lines.push(chip.get_name().unwrap_or_else(|_| chip.prefix().to_string()));
+
+        for feat in chip {
+            let name = feat.name().to_string();
+            let label = feat.get_label().unwrap_or_else(|_| name.clone());
+            let subfeats = feat.into_iter().map(|subfeat| match subfeat.get_value() {
+                Ok(value) => format!("{}={}", subfeat.name(), value),
+                Err(_) => format!("{}=?", subfeat.name()),
+            });
+            lines.push(format!("  {} ({}): {}", name, label, join(subfeats, " ")));
         }
     }
 
-    None
+    lines
 }
 
 lazy_static! {
@@ -294,7 +453,7 @@ fn show_dbms(dbms: i16) -> String {
         }
 
         &' '
-    }), &"")
+    }), "")
 }
 
 const BATTERY_LEVELS: [(u8, char); 4] = [
@@ -313,186 +472,602 @@ fn show_battery_icon(level: u8) -> char {
     ''
 }
 
-fn get_battery() -> Option<battery::Battery> {
-    if let Ok(manager) = battery::Manager::new() {
-        if let Ok(mut batteries) = manager.batteries() {
-            if let Some(Ok(battery)) = batteries.next() {
-                return Some(battery);
-            }
-        }
-    }
+fn get_battery() -> Result<battery::Battery, CommandError> {
+    let manager = battery::Manager::new()?;
+    let mut batteries = manager.batteries()?;
 
-    None
+    match batteries.next() {
+        Some(battery) => Ok(battery?),
+        None => Err(CommandError::NotAvailable("no battery".to_string())),
+    }
 }
 
+fn get_smart_attribute(device: &str, id: u8) -> Result<hdd::ata::data::attr::SmartAttribute, CommandError> {
+    let device = hdd::device::linux::Device::open(device)?;
+    let ata_device = ATADevice::new(SCSIDevice::new(device));
+    let attrs = ata_device.get_smart_attributes(&None)?;
 
+    attrs.into_iter().find(|attr| attr.id == id)
+        .ok_or_else(|| CommandError::NotAvailable(format!("no S.M.A.R.T. attribute {}", id)))
+}
 
 pub const LOADAVG:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |_| {
-        let la = procfs::LoadAverage::new();
-        match la {
-            Ok(la) => Some(format!("{:.2} {:.2}", la.one, la.five)),
-            Err(_) => None,
-        }
+    measure: |_| {
+        let la = procfs::LoadAverage::new()?;
+
+        Ok(Measurement::new(&[
+            ("one", la.one as f64, Unit::None),
+            ("five", la.five as f64, Unit::None),
+            ("fifteen", la.fifteen as f64, Unit::None),
+        ]))
+    },
+    format: |measurement, _| {
+        format!("{:.2} {:.2}", measurement.get("one").unwrap_or(f64::NAN), measurement.get("five").unwrap_or(f64::NAN))
     },
     pre_spaces: 0,
     post_spaces: 3,
 };
 
+const PRESSURE_DIR: &str = "/proc/pressure";
+const CGROUP_DIR: &str = "/sys/fs/cgroup";
+
+// /proc/pressure/<resource>, or <resource>.pressure of a cgroup v2 relative to /sys/fs/cgroup
+fn pressure_file(resource: &str, cgroup: Option<&str>) -> Result<path::PathBuf, CommandError> {
+    if !["cpu", "memory", "io"].contains(&resource) {
+        return Err(CommandError::Parse(format!("unknown resource {}, expected cpu, memory or io", resource)));
+    }
+
+    Ok(match cgroup {
+        // only plain names below the cgroup root, no way out of it
+        Some(cgroup) if path::Path::new(cgroup).components().any(|component| !matches!(component, path::Component::Normal(_))) =>
+            return Err(CommandError::Parse(format!("bad cgroup {}, expected a path relative to {}", cgroup, CGROUP_DIR))),
+        Some(cgroup) => path::Path::new(CGROUP_DIR).join(cgroup).join(resource.to_owned() + ".pressure"),
+        None => path::Path::new(PRESSURE_DIR).join(resource),
+    })
+}
+
+// avg10 of "some avg10=1.23 avg60=0.50 avg300=0.10 total=12345" and of the "full" line,
+// which cpu only has since 5.13
+fn parse_pressure(contents: &str) -> Result<Measurement, CommandError> {
+    let mut measurement = Measurement::new(&[]);
+
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = match fields.next() {
+            Some(kind) if kind == "some" || kind == "full" => kind,
+            _ => continue,
+        };
+        let avg10 = fields.find_map(|field| field.strip_prefix("avg10="))
+            .ok_or_else(|| CommandError::Parse(format!("no avg10 in \"{}\"", line)))?;
+
+        measurement.push(kind, f64::from_str(avg10)?, Unit::Percent);
+    }
+
+    match measurement.get("some") {
+        Some(some) => Ok(measurement.with_ratio(some / 100.0)),
+        None => Err(CommandError::Parse("no \"some\" line".to_string())),
+    }
+}
+
+pub const PRESSURE:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| {
+        let file = pressure_file(arg(args, 0, "resource")?, args.get(1).copied())?;
+        parse_pressure(&fs::read_to_string(file)?)
+    },
+    format: |measurement, _| {
+        // the small values matter here, unlike the usual percentages
+        match (measurement.get("some"), measurement.get("full")) {
+            (Some(some), Some(full)) => format!("{:.1}:{:.1}%", some, full),
+            (Some(some), None) => format!("{:.1}%", some),
+            _ => "?".to_string(),
+        }
+    },
+    pre_spaces: 0,
+    post_spaces: 2,
+};
+
 lazy_static! {
-    static ref CPU_LINE_REGEXP: Regex = Regex::new(r"^cpu(\d+) ").unwrap();
+    // the aggregate "cpu" line has no number
+    static ref CPU_LINE_REGEXP: Regex = Regex::new(r"^cpu(\d*) ").unwrap();
+}
+
+// the fields of a /proc/stat cpu line, in USER_HZ
+#[derive(Debug, PartialEq)]
+struct CpuTimes {
+    user: u64,
+    nice: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
+    // since 2.6.24 and 2.6.33, already counted in user and nice
+    guest: Option<u64>,
+    guest_nice: Option<u64>,
+}
+
+impl CpuTimes {
+    fn used(&self) -> u64 {
+        self.user + self.nice + self.system + self.irq + self.softirq + self.steal
+    }
+
+    fn total(&self) -> u64 {
+        self.used() + self.idle + self.iowait
+    }
+}
+
+// "cpu" or "cpuN" with its times, lines that don't parse are skipped
+fn parse_stat(contents: &str) -> Vec<(String, CpuTimes)> {
+    contents.lines().filter(|line| CPU_LINE_REGEXP.is_match(line)).filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        let name = fields.next()?.to_string();
+        let a = fields.map(u64::from_str).collect::<Result<Vec<u64>, _>>().ok()?;
+        if a.len() < 8 {
+            return None;
+        }
+
+        Some((name, CpuTimes {
+            user: a[0],
+            nice: a[1],
+            system: a[2],
+            idle: a[3],
+            iowait: a[4],
+            irq: a[5],
+            softirq: a[6],
+            steal: a[7],
+            guest: a.get(8).copied(),
+            guest_nice: a.get(9).copied(),
+        }))
+    }).collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum CpuMode {
+    PerCore,
+    // the aggregate line
+    Total,
+    // the N busiest cores
+    Top(usize),
+    Sparkline,
+}
+
+fn parse_cpu_mode(mode: &str) -> Result<CpuMode, CommandError> {
+    match mode {
+        "per-core" => Ok(CpuMode::PerCore),
+        "total" => Ok(CpuMode::Total),
+        "sparkline" => Ok(CpuMode::Sparkline),
+        mode if mode.starts_with("top-") => match usize::from_str(&mode["top-".len()..]) {
+            Ok(n) if n > 0 => Ok(CpuMode::Top(n)),
+            _ => Err(CommandError::Parse(format!("bad number of cores in {}", mode))),
+        },
+        mode => Err(CommandError::Parse(format!("unknown cpu mode {}, expected total, per-core, top-N or sparkline", mode))),
+    }
+}
+
+struct CpuUsage {
+    name: String,
+    percentage: f64,
+    freq_level: Option<f64>,
+}
+
+fn cpu_measurement(mut usages: Vec<CpuUsage>, mode: &CpuMode) -> Measurement {
+    if let CpuMode::Top(n) = mode {
+        // the busiest first, the ones without a value last
+        usages.sort_by(|a, b| b.percentage.partial_cmp(&a.percentage).unwrap_or_else(|| a.percentage.is_nan().cmp(&b.percentage.is_nan())));
+        usages.truncate(*n);
+    }
+
+    let mut measurement = Measurement::new(&[]);
+    for usage in &usages {
+        measurement.push(&usage.name, usage.percentage, Unit::Percent);
+        if let Some(level) = usage.freq_level {
+            measurement.push(&(usage.name.to_owned() + "_freq"), level, Unit::Percent);
+        }
+    }
+
+    match (mode, usages.first()) {
+        (CpuMode::Total, Some(usage)) if !usage.percentage.is_nan() => measurement.with_ratio(usage.percentage / 100.0),
+        _ => measurement,
+    }
 }
+
 pub const CPU:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |_| {
-        if let Ok(stat_file) = fs::File::open("/proc/stat") {
-            let mut linereader = LineReader::new(stat_file);
-
-            let mut cpuinfos: Vec<String> = vec![];
-
-            if let Ok(_) = linereader.for_each(|line| {
-                if let Ok(str_line) = std::str::from_utf8(line) {
-                    if let Some(caps) = CPU_LINE_REGEXP.captures(str_line) {
-                        let a: Vec<&str> = str_line.split(" ").collect();
-                        if a.len() >= 9 {
-                            if let Ok(_) = || -> Result<(), rust_decimal::Error> {
-                                let user = Decimal::from_str(a[1])?;
-                                let nice = Decimal::from_str(a[2])?;
-                                let system = Decimal::from_str(a[3])?;
-                                let idle = Decimal::from_str(a[4])?;
-                                let iowait = Decimal::from_str(a[5])?;
-                                let irq = Decimal::from_str(a[6])?;
-                                let softirq = Decimal::from_str(a[7])?;
-                                let steal = Decimal::from_str(a[8])?;
-
-                                let used = user + nice + system + irq + softirq + steal;
-                                let total = used + idle + iowait;
-
-                                let new_state = format!("{} {}", used, total);
-                                // save anyway, display only if there was an old state
-                                if let Ok(old_state) = persist_state(&("old".to_owned() + a[0]), &new_state) {
-                                    let old_state: Vec<&str> = old_state.split(" ").collect();
-                                    if old_state.len() == 2 {
-                                        let cpu_no = caps.get(1).unwrap().as_str();
-
-                                        let old_used = Decimal::from_str(old_state[0])?;
-                                        let old_total = Decimal::from_str(old_state[1])?;
-                                        cpuinfos.push(if total > old_total {
-                                            format!(
-                                                "{}{:.0}%",
-                                                cpu_freq_icon(cpu_no).unwrap_or("".to_string()),
-                                                Decimal::ONE_HUNDRED * (used - old_used) / (total - old_total)
-                                            )
-                                        } else {
-                                            "?".to_string()
-                                        });
-                                    }
-                                };
-
-                                Ok(())
-                            }() {};
-                        }
-                    }
-                }
+    measure: |args| {
+        let mode = parse_cpu_mode(args.first().copied().unwrap_or("per-core"))?;
+        let with_freq = match args.get(1).copied().unwrap_or("freq") {
+            "freq" => true,
+            "nofreq" => false,
+            freq => return Err(CommandError::Parse(format!("unexpected {}, expected freq or nofreq", freq))),
+        };
+
+        let stat = parse_stat(&fs::read_to_string("/proc/stat")?);
+        // several cpu entries with other modes don't eat each other's samples
+        let key_base = state::key("cpu", &args.join(":"));
+
+        let mut usages = vec![];
+        // every line is saved anyway, the first run is #AGAIN# like in the other delta commands
+        let mut error = None;
+        for (name, times) in &stat {
+            let cpu_no = &name["cpu".len()..];
+            if cpu_no.is_empty() != (mode == CpuMode::Total) {
+                continue;
+            }
 
-                Ok(true)
-            }) {
-                return Some(cpuinfos.join(" "));
+            let delta = match delta::track(&state::key(&key_base, name), &[times.used(), times.total()], u64::MAX) {
+                Ok(delta) => delta,
+                Err(e) => {
+                    error.get_or_insert(e);
+                    continue;
+                },
+            };
+
+            let percentage = match delta.as_ref().map(|delta| (delta.counters[0], delta.counters[1])) {
+                Some((Some(used), Some(total))) if total > 0 => 100.0 * used as f64 / total as f64,
+                // reset, e.g. a CPU was brought back online
+                _ => f64::NAN,
+            };
+
+            let freq_level = match (with_freq, cpu_no) {
+                (false, _) => None,
+                // the average of all the cores
+                (true, "") => {
+                    let levels: Vec<f64> = stat.iter().filter_map(|(name, _)| cpu_freq_level(&name["cpu".len()..]).ok()).collect();
+                    if levels.is_empty() { None } else { Some(levels.iter().sum::<f64>() / levels.len() as f64) }
+                },
+                (true, cpu_no) => cpu_freq_level(cpu_no).ok(),
+            };
+
+            usages.push(CpuUsage { name: name.clone(), percentage, freq_level });
+        }
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(cpu_measurement(usages, &mode)),
+        }
+    },
+    format: |measurement, args| {
+        let usages = measurement.values.iter().filter(|value| !value.name.ends_with("_freq"));
+
+        if let Ok(CpuMode::Sparkline) = parse_cpu_mode(args.first().copied().unwrap_or("per-core")) {
+            // one marker for all the cores, there's no room for more
+            let levels: Vec<f64> = measurement.values.iter().filter(|value| value.name.ends_with("_freq")).map(|value| value.amount).collect();
+            let freq_icon = if levels.is_empty() { "" } else { freq_level_icon(levels.iter().sum::<f64>() / levels.len() as f64) };
+
+            return freq_icon.to_string() + &format_sparkline(usages.map(|value| value.amount));
+        }
+
+        join(usages.map(|value| {
+            if value.amount.is_nan() {
+                return "?".to_string();
             }
+
+            let freq_icon = match measurement.get(&(value.name.to_owned() + "_freq")) {
+                Some(level) => freq_level_icon(level),
+                None => "",
+            };
+
+            freq_icon.to_string() + &format_value(value)
+        }), " ")
+    },
+    pre_spaces: 0,
+    post_spaces: 2,
+};
+
+// user without the guest time, it's counted in both
+fn breakdown_counters(times: &CpuTimes) -> Vec<u64> {
+    let guest = times.guest.unwrap_or(0) + times.guest_nice.unwrap_or(0);
+    let mut counters = vec![
+        times.total(),
+        (times.user + times.nice).saturating_sub(guest),
+        times.system + times.irq + times.softirq,
+        times.iowait,
+        times.steal,
+    ];
+    if times.guest.is_some() {
+        counters.push(guest);
+    }
+
+    counters
+}
+
+const BREAKDOWN_NAMES: &[&str] = &["user", "system", "iowait", "steal", "guest"];
+
+// the counters are deltas in the order of breakdown_counters, the total first
+fn breakdown_measurement(counters: &[Option<u64>]) -> Measurement {
+    let total = match counters.first() {
+        Some(Some(total)) if *total > 0 => Some(*total as f64),
+        _ => None,
+    };
+
+    let mut measurement = Measurement::new(&[]);
+    for (name, counter) in BREAKDOWN_NAMES.iter().zip(counters.iter().skip(1)) {
+        let percentage = match (counter, total) {
+            (Some(counter), Some(total)) => 100.0 * *counter as f64 / total,
+            // reset, e.g. a CPU was brought back online
+            _ => f64::NAN,
+        };
+        measurement.push(name, percentage, Unit::Percent);
+    }
+
+    measurement
+}
+
+pub const CPU_BREAKDOWN:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| {
+        let cpu = args.first().copied().unwrap_or("cpu");
+
+        let stat = parse_stat(&fs::read_to_string("/proc/stat")?);
+        let (_, times) = stat.iter().find(|(name, _)| name == cpu)
+            .ok_or_else(|| CommandError::NotAvailable(format!("no {} in /proc/stat", cpu)))?;
+
+        let counters = breakdown_counters(times);
+        match delta::track(&state::key("cpu_breakdown", cpu), &counters, u64::MAX)? {
+            Some(delta) => Ok(breakdown_measurement(&delta.counters)),
+            None => Ok(breakdown_measurement(&vec![None; counters.len()])),
         }
+    },
+    format: |measurement, _| {
+        // the abbreviations of top
+        join(measurement.values.iter().map(|value| {
+            let abbreviation = match value.name.as_str() {
+                "user" => "us",
+                "system" => "sy",
+                "iowait" => "wa",
+                "steal" => "st",
+                _ => "gu",
+            };
 
-        None
+            abbreviation.to_string() + &format_value(value)
+        }), " ")
     },
     pre_spaces: 0,
     post_spaces: 2,
 };
 
-lazy_static! {
-    static ref MEMINFO: procfs::ProcResult<procfs::Meminfo> = procfs::Meminfo::new();
+// read once per tick, shared by MEM and ZRAM
+static MEMINFO: Mutex<Option<Arc<procfs::ProcResult<procfs::Meminfo>>>> = Mutex::new(None);
+
+fn get_meminfo() -> Arc<procfs::ProcResult<procfs::Meminfo>> {
+    MEMINFO.lock().unwrap().get_or_insert_with(|| Arc::new(procfs::Meminfo::new())).clone()
 }
 pub const MEM:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |_| {
-        match &*MEMINFO {
-            Ok(meminfo) => {
-                let mem_available = meminfo.mem_available.unwrap_or(0) as u64;
-                let mem_total = meminfo.mem_total as u64;
+    measure: |_| {
+        let meminfo = get_meminfo();
+        let meminfo = meminfo.as_ref().as_ref()?;
 
-                Some(format_two_amounts(mem_total - mem_available, mem_total, "/", true))
-            },
-            Err(_) => None,
-        }
+        let mem_available = meminfo.mem_available.unwrap_or(0);
+        let mem_total = meminfo.mem_total;
+
+        let mem_used = mem_total - mem_available;
+        let ratio = mem_used as f64 / mem_total as f64;
+
+        Ok(Measurement::new(&[
+            ("used", mem_used as f64, Unit::Bytes),
+            ("total", mem_total as f64, Unit::Bytes),
+        ]).with_ratio(ratio).with_severity(Severity::from_ratio(ratio, 0.8, 0.95)))
     },
+    format: |measurement, _| format_slash_pair(measurement),
     pre_spaces: 0,
     post_spaces: 3,
 };
 
 pub const ZRAM:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |_| {
-        match &*MEMINFO {
-            Ok(meminfo) => {
-                let swap_free = meminfo.swap_free;
-                let swap_total = meminfo.swap_total;
-
-                let mut total_comp: u64 = 0;
-
-                for i in 0.. {
-                    match fs::read_to_string("/sys/devices/virtual/block/zram".to_string() + &i.to_string() + "/mm_stat") {
-                        Ok(contents) => {
-                            let a: Vec<&str> = contents.split(" ").collect();
-                            if a.len() >= 2 {
-                                if let Ok(comp) = u64::from_str(a[1]) {
-                                    total_comp += comp;
-                                }
-                            }
-                        },
-                        // assume that the numeration is contiguous, so if the file
-                        // on this interation can't be opened then all they were passed
-                        Err(_) => { break; }
+    measure: |_| {
+        let meminfo = get_meminfo();
+        let meminfo = meminfo.as_ref().as_ref()?;
+
+        let swap_free = meminfo.swap_free;
+        let swap_total = meminfo.swap_total;
+
+        let mut total_comp: u64 = 0;
+
+        for i in 0.. {
+            match fs::read_to_string("/sys/devices/virtual/block/zram".to_string() + &i.to_string() + "/mm_stat") {
+                Ok(contents) => {
+                    let a: Vec<&str> = contents.split(" ").collect();
+                    if a.len() >= 2 {
+                        if let Ok(comp) = u64::from_str(a[1]) {
+                            total_comp += comp;
+                        }
                     }
-                }
-
-                Some(format_two_amounts(total_comp, (swap_total - swap_free) as u64, ":", true))
-            },
-            Err(_) => None,
+                },
+                // assume that the numeration is contiguous, so if the file
+                // on this interation can't be opened then all they were passed
+                Err(_) => { break; }
+            }
         }
+
+        let swap_used = swap_total - swap_free;
+
+        Ok(Measurement::new(&[
+            ("compressed", total_comp as f64, Unit::Bytes),
+            ("swap_used", swap_used as f64, Unit::Bytes),
+        ]))
     },
+    format: |measurement, _| format_colon_pair(measurement),
     pre_spaces: 0,
     post_spaces: 3,
 };
 
 const RADEON_VRAM_BLOCK_SIZE: u64 = 4096;
+const DRM_DIR: &str = "/sys/class/drm";
+const DEBUG_DRI_DIR: &str = "/sys/kernel/debug/dri";
+
+// used and total bytes from the last line, "total: 262144, used 12345 free 249799"
+fn parse_radeon_vram_mm(contents: &str) -> Result<(u64, u64), CommandError> {
+    let last_line = contents.lines().last()
+        .ok_or_else(|| CommandError::Parse("radeon_vram_mm is empty".to_string()))?;
+
+    let a: Vec<&str> = last_line.split(" ").collect();
+    if a.len() < 4 {
+        return Err(CommandError::Parse(format!("unexpected radeon_vram_mm line \"{}\"", last_line)));
+    }
+
+    let used = u64::from_str(a[3])?;
+
+    let mut total = a[1].to_string();
+    // strip the comma
+    total.pop();
+    let total = u64::from_str(&total)?;
+
+    Ok((used * RADEON_VRAM_BLOCK_SIZE, total * RADEON_VRAM_BLOCK_SIZE))
+}
+
+fn read_radeon_vram(debug_dri_dir: &path::Path, card: u32) -> Result<Measurement, CommandError> {
+    // there's no too much contents (around 32 kB for me), so it's easier
+    // to read it all rather than messing with LineReader and copying lines
+    // crawled by it
+    let contents = fs::read_to_string(debug_dri_dir.join(card.to_string()).join("radeon_vram_mm"))?;
+    let (used, total) = parse_radeon_vram_mm(&contents)?;
+
+    Ok(Measurement::new(&[
+        ("used", used as f64, Unit::Bytes),
+        ("total", total as f64, Unit::Bytes),
+    ]).with_ratio(used as f64 / total as f64))
+}
+
+fn read_u64(path: &path::Path) -> Result<u64, CommandError> {
+    let mut contents = fs::read_to_string(path)?;
+    trim_trailing_newline(&mut contents);
+    Ok(u64::from_str(&contents)?)
+}
+
+// amdgpu reports the memory in sysfs, no root needed
+fn read_amdgpu_mem(drm_dir: &path::Path, card: u32) -> Result<Measurement, CommandError> {
+    let device = drm_dir.join(format!("card{}", card)).join("device");
+
+    let used = read_u64(&device.join("mem_info_vram_used"))?;
+    let total = read_u64(&device.join("mem_info_vram_total"))?;
+    let mut measurement = Measurement::new(&[
+        ("used", used as f64, Unit::Bytes),
+        ("total", total as f64, Unit::Bytes),
+    ]).with_ratio(used as f64 / total as f64);
+
+    // the GTT is system memory mapped for the GPU, shown when there's one
+    if let (Ok(gtt_used), Ok(gtt_total)) = (read_u64(&device.join("mem_info_gtt_used")), read_u64(&device.join("mem_info_gtt_total"))) {
+        measurement.push("gtt_used", gtt_used as f64, Unit::Bytes);
+        measurement.push("gtt_total", gtt_total as f64, Unit::Bytes);
+    }
+
+    Ok(measurement)
+}
+
+fn read_gpu_mem(drm_dir: &path::Path, debug_dri_dir: &path::Path, card: u32) -> Result<Measurement, CommandError> {
+    match read_amdgpu_mem(drm_dir, card) {
+        // not amdgpu, maybe radeon
+        Err(CommandError::NotAvailable(_)) => read_radeon_vram(debug_dri_dir, card),
+        measurement => measurement,
+    }
+}
+
 pub const RADEON_VRAM:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |_| {
-        // there's no too much contents (around 32 kB for me), so it's easier
-        // to read it all rather than messing with LineReader and copying lines
-        // crawled by it
-        if let Ok(stdout) = fs::read_to_string("/sys/kernel/debug/dri/0/radeon_vram_mm") {
-            let last_line = stdout.lines().last();
-
-            if let Some(last_line) = last_line {
-                let a: Vec<&str> = last_line.split(" ").collect();
-                if a.len() >= 4 {
-                    if let Ok(result) = || -> Result<String, std::num::ParseIntError> {
-                        let used = u64::from_str(a[3])?;
-
-                        let mut total = a[1].to_string();
-                        // strip the comma
-                        total.pop();
-                        let total = u64::from_str(&total)?;
-
-                        Ok(format_two_amounts(used * RADEON_VRAM_BLOCK_SIZE, total * RADEON_VRAM_BLOCK_SIZE, "/", true))
-                    }() {
-                        return Some(result);
-                    }
-                }
-            }
+    measure: |_| {
+        read_radeon_vram(path::Path::new(DEBUG_DRI_DIR), 0)
+    },
+    format: |measurement, _| format_slash_pair(measurement),
+    pre_spaces: 0,
+    post_spaces: 2,
+};
+
+pub const GPU_MEM:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| {
+        let card = u32::from_str(arg(args, 0, "card")?)?;
+        read_gpu_mem(path::Path::new(DRM_DIR), path::Path::new(DEBUG_DRI_DIR), card)
+    },
+    format: |measurement, _| {
+        match (measurement.get("gtt_used"), measurement.get("gtt_total")) {
+            (Some(gtt_used), Some(gtt_total)) => format_slash_pair(measurement) + " " +
+                &format_two_amounts(gtt_used as u64, gtt_total as u64, "/", true),
+            _ => format_slash_pair(measurement),
         }
+    },
+    pre_spaces: 0,
+    post_spaces: 2,
+};
 
-        None
+// position of the active level among "0: 300Mhz\n1: 1200Mhz *\n..." in percents, and its frequency
+fn parse_dpm_level(contents: &str) -> Option<(f64, u64)> {
+    let levels: Vec<&str> = contents.lines().filter(|line| !line.trim().is_empty()).collect();
+    let active = levels.iter().position(|line| line.trim_end().ends_with('*'))?;
+
+    let mhz = levels[active].split_whitespace().nth(1)?.to_lowercase();
+    let mhz = u64::from_str(mhz.trim_end_matches("mhz")).ok()?;
+
+    if levels.len() < 2 {
+        return Some((0.0, mhz));
+    }
+
+    Some((100.0 * active as f64 / (levels.len() - 1) as f64, mhz))
+}
+
+fn read_amdgpu_busy(device: &path::Path) -> Result<Measurement, CommandError> {
+    let busy = read_u64(&device.join("gpu_busy_percent"))?;
+    let mut measurement = Measurement::new(&[("busy", busy as f64, Unit::Percent)])
+        .with_ratio(busy as f64 / 100.0);
+
+    // the clock is optional, some cards don't allow reading it
+    if let Some((level, mhz)) = fs::read_to_string(device.join("pp_dpm_sclk")).ok().as_deref().and_then(parse_dpm_level) {
+        measurement.push("freq", level, Unit::Percent);
+        measurement.push("mhz", mhz as f64, Unit::None);
+    }
+
+    Ok(measurement)
+}
+
+// i915 has no load in sysfs, only the current frequency
+fn read_i915_freq(card_dir: &path::Path) -> Result<Measurement, CommandError> {
+    let cur = read_u64(&card_dir.join("gt_cur_freq_mhz"))?;
+    let max = read_u64(&card_dir.join("gt_max_freq_mhz"))?;
+    let min = read_u64(&card_dir.join("gt_min_freq_mhz")).unwrap_or(0);
+
+    let level = if max <= min {
+        0.0
+    } else {
+        100.0 * (cur as f64 - min as f64) / (max - min) as f64
+    };
+
+    Ok(Measurement::new(&[
+        ("freq", level, Unit::Percent),
+        ("mhz", cur as f64, Unit::None),
+    ]))
+}
+
+fn read_gpu_busy(drm_dir: &path::Path, card: u32) -> Result<Measurement, CommandError> {
+    let card_dir = drm_dir.join(format!("card{}", card));
+
+    match read_amdgpu_busy(&card_dir.join("device")) {
+        // not amdgpu, maybe i915
+        Err(CommandError::NotAvailable(_)) => read_i915_freq(&card_dir),
+        measurement => measurement,
+    }
+}
+
+pub const GPU_BUSY:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| {
+        let card = u32::from_str(arg(args, 0, "card")?)?;
+        read_gpu_busy(path::Path::new(DRM_DIR), card)
+    },
+    format: |measurement, _| {
+        let freq_icon = match measurement.get("freq") {
+            Some(level) => freq_level_icon(level),
+            None => "",
+        };
+
+        match (measurement.values.iter().find(|value| value.name == "busy"), measurement.get("mhz")) {
+            (Some(busy), _) => freq_icon.to_string() + &format_value(busy),
+            (None, Some(mhz)) => format!("{}{}MHz", freq_icon, mhz),
+            (None, None) => "?".to_string(),
+        }
     },
     pre_spaces: 0,
     post_spaces: 2,
@@ -500,105 +1075,130 @@ pub const RADEON_VRAM:StaticIconCommand = StaticIconCommand {
 
 pub const TRAFFIC:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |args| {
-        if args.len() < 1 {
-            return None;
-        }
-
-        let traffic = fetch_traffic_cached(args[0]);
+    measure: |args| {
+        let iface = arg(args, 0, "interface")?;
+        let traffic = resolve_iface(iface).and_then(|resolved| {
+            let traffic = fetch_traffic_cached(&resolved)?;
+            Ok(label_auto(iface, &resolved, Measurement::new(&[
+                ("rx", traffic.rx as f64, Unit::Bytes),
+                ("tx", traffic.tx as f64, Unit::Bytes),
+            ])))
+        });
 
         match traffic {
-            Ok(traffic) => Some(format_two_amounts(traffic.rx, traffic.tx, ":", true)),
-            Err(msg) => Some(msg)
+            Err(CommandError::NotAvailable(_)) => Ok(Measurement::message(DISCONNECTED)),
+            traffic => traffic,
         }
     },
+    format: |measurement, _| format_colon_pair(measurement),
     pre_spaces: 0,
     post_spaces: 2,
 };
 
-pub const NETWORK_SPEED:StaticIconCommand = StaticIconCommand {
-    icon: '',
-    call: |args| {
-        if args.len() < 1 {
-            return None;
-        }
+// keyed by the argument and the resolved interface, so a switch doesn't mix
+// the counters and "auto" doesn't eat the samples of the same interface by name
+fn network_speed(iface: &str, resolved: &str, traffic: MaybeTraffic) -> Result<Measurement, CommandError> {
+    let traffic = match traffic {
+        Ok(traffic) => traffic,
+        Err(CommandError::NotAvailable(_)) => return Ok(Measurement::message(DISCONNECTED)),
+        Err(e) => return Err(e),
+    };
 
-        let traffic = fetch_traffic_cached(args[0]);
+    let rates = delta::rates(&state::key("network_speed", &format!("{}:{}", iface, resolved)), &[traffic.rx, traffic.tx], u64::MAX)?;
 
-        match traffic {
-            Ok(traffic) => {
-                // save anyway, display only if there was an old state
-                let new_state = format!("{} {}", traffic.rx_string, traffic.tx_string);
-                if let Ok(old_state) = persist_state("network-speed-stat", &new_state) {
-                    let old_state: Vec<&str> = old_state.split(" ").collect();
-                    if old_state.len() == 2 {
-                        if let Ok(result) = || -> Result<String, std::num::ParseIntError> {
-                            let new_rx = traffic.rx;
-                            let new_tx = traffic.tx;
-                            let old_rx = u64::from_str(old_state[0])?;
-                            let old_tx = u64::from_str(old_state[1])?;
-
-                            // TODO: fix a possible panic here
-                            Ok(format_two_amounts(new_rx - old_rx, new_tx - old_tx, ":", true))
-                        }() {
-                            return Some(result);
-                        }
-                    }
-                }
+    Ok(Measurement::new(&[
+        ("rx", rates[0], Unit::BytesPerSecond),
+        ("tx", rates[1], Unit::BytesPerSecond),
+    ]))
+}
 
-                None
-            },
-            Err(msg) => Some(msg)
+pub const NETWORK_SPEED:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| {
+        let iface = arg(args, 0, "interface")?;
+        match resolve_iface(iface) {
+            Ok(resolved) => network_speed(iface, &resolved, fetch_traffic_cached(&resolved))
+                .map(|measurement| label_auto(iface, &resolved, measurement)),
+            Err(CommandError::NotAvailable(_)) => Ok(Measurement::message(DISCONNECTED)),
+            Err(e) => Err(e),
         }
     },
+    format: |measurement, _| format_colon_pair(measurement),
     pre_spaces: 0,
     post_spaces: 3,
 };
 
 pub const RADEON_TEMPERATURE:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |_| {
-        get_chip_temperature("radeon-pci-0100", "temp1")
+    measure: |_| {
+        get_chip_temperature(&[("radeon-pci-0100", Some("temp1"))])
     },
+    format: |measurement, _| format_single(measurement),
     pre_spaces: 0,
     post_spaces: 2,
 };
 
 pub const AMD_K10_TEMPERATURE:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |_| {
-        get_chip_temperature("k10temp-pci-00c3", "temp1")
+    measure: |_| {
+        get_chip_temperature(&[("k10temp-pci-00c3", Some("temp1"))])
     },
+    format: |measurement, _| format_single(measurement),
     pre_spaces: 1,
     post_spaces: 3,
 };
 
+pub const TEMPERATURE:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| {
+        let chip = arg(args, 0, "chip")?;
+        get_chip_temperature(&temperature_probes(chip, args.get(1).copied())?)
+    },
+    format: |measurement, _| format_single(measurement),
+    pre_spaces: 0,
+    post_spaces: 2,
+};
+
+pub const FAN:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| measure_sensor(&FAN_SENSOR, args),
+    format: |measurement, _| format_single(measurement),
+    pre_spaces: 0,
+    post_spaces: 2,
+};
+
+pub const VOLTAGE:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| measure_sensor(&VOLTAGE_SENSOR, args),
+    format: |measurement, _| format_single(measurement),
+    pre_spaces: 0,
+    post_spaces: 2,
+};
+
+pub const POWER:StaticIconCommand = StaticIconCommand {
+    icon: '',
+    measure: |args| measure_sensor(&POWER_SENSOR, args),
+    format: |measurement, _| format_single(measurement),
+    pre_spaces: 0,
+    post_spaces: 2,
+};
+
 const TEMPERATURE_CELSIUS: u8 = 194;
 pub const ATA_HDDTEMP:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |args| {
-        if args.len() < 1 {
-            return None;
-        }
-
-        if let Ok(device) = hdd::device::linux::Device::open(args[0]) {
-            let ata_device = ATADevice::new(SCSIDevice::new(device));
-            if let Ok(attrs) = ata_device.get_smart_attributes(&None) {
-                if let Some(attr) = attrs.iter().find(|attr| attr.id == TEMPERATURE_CELSIUS) {
-                    return match attr.raw {
-                        HDDRaw::CelsiusMinMax{current, ..} =>
-                            return Some(format!(TEMPERATURE_FORMAT!(), current as f64)),
-                        // the value seems to be 0x00000max0min0cur
-                        HDDRaw::Raw64(raw) =>
-                            return Some(format!(TEMPERATURE_FORMAT!(), (raw & 0xff) as f64)),
-                        _ => None
-                    }
-                }
-            }
+    measure: |args| {
+        let attr = get_smart_attribute(arg(args, 0, "device")?, TEMPERATURE_CELSIUS)?;
+
+        match attr.raw {
+            HDDRaw::CelsiusMinMax{current, ..} =>
+                Ok(Measurement::new(&[("temperature", current as f64, Unit::Celsius)])),
+            // the value seems to be 0x00000max0min0cur
+            HDDRaw::Raw64(raw) =>
+                Ok(Measurement::new(&[("temperature", (raw & 0xff) as f64, Unit::Celsius)])),
+            _ => Err(CommandError::Parse(format!("unexpected S.M.A.R.T. attribute {} format", TEMPERATURE_CELSIUS))),
         }
-
-        None
     },
+    format: |measurement, _| format_single(measurement),
     pre_spaces: 0,
     post_spaces: 3,
 };
@@ -606,344 +1206,410 @@ pub const ATA_HDDTEMP:StaticIconCommand = StaticIconCommand {
 const GSENSE_ERROR_RATE: u8 = 191;
 pub const ATA_GSENSE_ERROR_RATE:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |args| {
-        if args.len() < 1 {
-            return None;
-        }
-
-        if let Ok(device) = hdd::device::linux::Device::open(args[0]) {
-            let ata_device = ATADevice::new(SCSIDevice::new(device));
-            if let Ok(attrs) = ata_device.get_smart_attributes(&None) {
-                if let Some(attr) = attrs.iter().find(|attr| attr.id == GSENSE_ERROR_RATE) {
-                    return match attr.raw {
-                        // two packed uint16
-                        HDDRaw::Raw64(raw) => {
-                            let raw: u64 = raw.try_into().unwrap();
-                            return Some(format_two_amounts((raw >> 16) & 0xffff, raw & 0xffff, ":", false))
-                        },
-                        _ => None
-                    }
-                }
-            }
+    measure: |args| {
+        let attr = get_smart_attribute(arg(args, 0, "device")?, GSENSE_ERROR_RATE)?;
+
+        match attr.raw {
+            // two packed uint16
+            HDDRaw::Raw64(raw) => {
+                let high = (raw >> 16) & 0xffff;
+                let low = raw & 0xffff;
+                Ok(Measurement::new(&[
+                    ("high", high as f64, Unit::None),
+                    ("low", low as f64, Unit::None),
+                ]))
+            },
+            _ => Err(CommandError::Parse(format!("unexpected S.M.A.R.T. attribute {} format", GSENSE_ERROR_RATE))),
         }
-
-        None
     },
+    format: |measurement, _| format_colon_pair(measurement),
     pre_spaces: 1,
     post_spaces: 4,
 };
 
 pub const WIRELESS_SIGNAL:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |args| {
-        if args.len() < 1 {
-            return None;
-        }
-
-        let interface = args[0];
-
-        if let Ok(stat_file) = fs::File::open("/proc/net/wireless") {
-            let mut linereader = LineReader::new(stat_file);
-
-            while let Some(Ok(line)) = linereader.next_line() {
-                if let Ok(str_line) = std::str::from_utf8(line) {
-                    let mut token_iter = str_line.split_whitespace();
-                    if let Some(interface_column) = token_iter.next() {
-                        if interface_column.starts_with(interface) {
-                            if let Some(level) = token_iter.nth(2) {
-                                let mut level = level.to_string();
-
-                                if level.ends_with('.') {
-                                    level.pop();
-                                }
-
-                                if let Ok(int_level) = level.parse::<i16>() {
-                                    return Some(format!("{} {}", show_dbms(int_level), level));
-                                }
-                            }
+    measure: |args| {
+        let iface = arg(args, 0, "interface")?;
+        let interface = &resolve_iface(iface)?;
+
+        let stat_file = fs::File::open("/proc/net/wireless")?;
+        let mut linereader = LineReader::new(stat_file);
+
+        while let Some(line) = linereader.next_line() {
+            let str_line = std::str::from_utf8(line?)
+                .map_err(|e| CommandError::Parse(e.to_string()))?;
+            let mut token_iter = str_line.split_whitespace();
+            if let Some(interface_column) = token_iter.next() {
+                if interface_column.starts_with(interface) {
+                    if let Some(level) = token_iter.nth(2) {
+                        let mut level = level.to_string();
+
+                        if level.ends_with('.') {
+                            level.pop();
                         }
+
+                        let measurement = Measurement::new(&[("level", level.parse::<i16>()? as f64, Unit::DBm)]);
+                        return Ok(label_auto(iface, interface, measurement));
                     }
                 }
             }
         }
 
-        None
+        Err(CommandError::NotAvailable(format!("no {} in /proc/net/wireless", interface)))
+    },
+    format: |measurement, _| {
+        match measurement.get("level") {
+            Some(level) => format!("{} {}", show_dbms(level as i16), level),
+            None => "?".to_string(),
+        }
     },
     pre_spaces: 0,
     post_spaces: 3,
 };
 
-const LINUX_BLOCK_SIZE: u64 = 512;
+const LINUX_BLOCK_SIZE: u64 = 512;
+// the kernel wraps the sector counters, not the bytes, so those are tracked
+fn disk_io_speed(disk_name: &str, sectors_read: u64, sectors_written: u64, max: u64) -> Result<Measurement, CommandError> {
+    let rates = delta::rates(&state::key("disk_io_speed", disk_name), &[sectors_read, sectors_written], max)?;
+
+    Ok(Measurement::new(&[
+        ("read", rates[0] * LINUX_BLOCK_SIZE as f64, Unit::BytesPerSecond),
+        ("written", rates[1] * LINUX_BLOCK_SIZE as f64, Unit::BytesPerSecond),
+    ]))
+}
+
 pub const DISK_IO_SPEED:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |args| {
-        if args.len() < 1 {
-            return None;
-        }
+    measure: |args| {
+        let disk_name = arg(args, 0, "disk")?;
 
-        let disk_name = args[0];
-
-        if let Ok(diskstats) = procfs::diskstats() {
-            if let Some(diskstat) = diskstats.iter().find(|diskstat| diskstat.name == args[0]) {
-                let read_bytes = (diskstat.sectors_read as u64) * LINUX_BLOCK_SIZE;
-                let written_bytes = (diskstat.sectors_written as u64) * LINUX_BLOCK_SIZE;
-
-                let new_state = format!("{} {}", read_bytes, written_bytes);
-                // save anyway, display only if there was an old state
-                if let Ok(old_state) = persist_state(&("old".to_owned() + disk_name), &new_state) {
-                    let old_state: Vec<&str> = old_state.split(" ").collect();
-                    if old_state.len() == 2 {
-                        if let Ok(result) = || -> Result<String, std::num::ParseIntError> {
-                            let old_read_bytes = u64::from_str(old_state[0])?;
-                            let old_written_bytes = u64::from_str(old_state[1])?;
-
-                            Ok(format_two_amounts(read_bytes - old_read_bytes, written_bytes - old_written_bytes, ":", true))
-                        }() {
-                            return Some(result);
-                        }
-                    }
-                }
-            }
-        }
+        let diskstats = procfs::diskstats()?;
+        let diskstat = diskstats.iter().find(|diskstat| diskstat.name == disk_name)
+            .ok_or_else(|| CommandError::NotAvailable(format!("no disk {} in /proc/diskstats", disk_name)))?;
 
-        None
+        disk_io_speed(disk_name, diskstat.sectors_read as u64, diskstat.sectors_written as u64, delta::ULONG_MAX)
     },
+    format: |measurement, _| format_colon_pair(measurement),
     pre_spaces: 0,
     post_spaces: 3,
 };
 
 pub const FS_FREE:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |args| {
-        if args.len() < 1 {
-            return None;
-        }
-
-        let fs_root = args[0];
-
-        if let Ok(c_fs_root) = CString::new(fs_root) {
-            unsafe {
-                let mut statvfs = mem::zeroed();
-                if libc::statvfs(c_fs_root.as_ptr(), &mut statvfs) >= 0 {
-                    let blocksize = if statvfs.f_frsize != 0 {
-                        statvfs.f_frsize as u64
-                    } else {
-                        statvfs.f_bsize as u64
-                    };
-                    let free = blocksize * (statvfs.f_bavail as u64);
-                    let total = blocksize * (statvfs.f_blocks as u64);
-
-                    return Some(format_two_amounts(free, total, "/", true));
-                }
+    measure: |args| {
+        let fs_root = arg(args, 0, "mountpoint")?;
+
+        let c_fs_root = CString::new(fs_root)?;
+        unsafe {
+            let mut statvfs = mem::zeroed();
+            if libc::statvfs(c_fs_root.as_ptr(), &mut statvfs) < 0 {
+                return Err(CommandError::from(io::Error::last_os_error()));
             }
-        }
 
-        None
+            let blocksize = if statvfs.f_frsize != 0 {
+                statvfs.f_frsize as u64
+            } else {
+                statvfs.f_bsize as u64
+            };
+            let free = blocksize * (statvfs.f_bavail as u64);
+            let total = blocksize * (statvfs.f_blocks as u64);
+
+            let used_ratio = 1.0 - free as f64 / total as f64;
+
+            Ok(Measurement::new(&[
+                ("free", free as f64, Unit::Bytes),
+                ("total", total as f64, Unit::Bytes),
+            ]).with_ratio(used_ratio).with_severity(Severity::from_ratio(used_ratio, 0.9, 0.97)))
+        }
     },
+    format: |measurement, _| format_slash_pair(measurement),
     pre_spaces: 0,
     post_spaces: 2,
 };
 
+fn get_ups_var(conn: &mut Connection, ups: &str, name: &str) -> Result<f64, CommandError> {
+    match conn.get_var(ups, name)? {
+        rups::Variable::Other((_, value)) => Ok(value.parse()?),
+        variable => Err(CommandError::Parse(format!("unexpected {} value {:?}", name, variable))),
+    }
+}
+
 pub const UPS_VOLTAGE:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |args| {
-        if args.len() < 1 {
-            return None;
-        }
+    measure: |args| {
+        let ups = arg(args, 0, "ups")?;
 
         let config = ConfigBuilder::new().build();
-        if let Ok(mut conn) = Connection::new(&config) {
-           if let Ok(rups::Variable::Other((_, input_voltage))) = conn.get_var(args[0], "input.voltage") {
-               if let Ok(rups::Variable::Other((_, output_voltage))) = conn.get_var(args[0], "output.voltage") {
-                   return Some(format!("{}→{}V", input_voltage.split(".").next()?, output_voltage.split(".").next()?));
-               }
-           }
-        }
+        let mut conn = Connection::new(&config)?;
 
-        None
+        Ok(Measurement::new(&[
+            ("input", get_ups_var(&mut conn, ups, "input.voltage")?, Unit::Volts),
+            ("output", get_ups_var(&mut conn, ups, "output.voltage")?, Unit::Volts),
+        ]))
+    },
+    format: |measurement, _| {
+        format!(
+            "{:.0}→{:.0}V",
+            measurement.get("input").unwrap_or(f64::NAN).trunc(),
+            measurement.get("output").unwrap_or(f64::NAN).trunc(),
+        )
     },
     pre_spaces: 0,
     post_spaces: 3,
 };
 
 pub const BATTERY:DynamicIconCommand = DynamicIconCommand {
-    call: |_| {
-        if let Some(battery) = get_battery() {
-            let state = battery.state_of_charge();
-            let int_state = (state.value * 100.0) as u8;
-            let energy = battery.energy();
-            let energy_full = battery.energy_full();
-
-            return Some(DynamicIconCommandOutput {
-                icon: show_battery_icon(int_state),
-                text: format!("{:.1}/{:.1}Wh", energy.get::<watt_hour>(), energy_full.get::<watt_hour>()),
-                bar: Some(int_state),
-                pre_spaces: 0,
-                post_spaces: 2,
-            });
-        }
-
-        None
+    icon: |measurement| {
+        show_battery_icon(measurement.bar().unwrap_or(0))
+    },
+    measure: |_| {
+        let battery = get_battery()?;
+        let state = battery.state_of_charge().value as f64;
+        let energy = battery.energy();
+        let energy_full = battery.energy_full();
+
+        Ok(Measurement::new(&[
+            ("energy", energy.get::<watt_hour>() as f64, Unit::WattHours),
+            ("energy_full", energy_full.get::<watt_hour>() as f64, Unit::WattHours),
+        ]).with_ratio(state).with_severity(Severity::from_ratio(1.0 - state, 0.8, 0.9)))
     },
+    format: |measurement, _| {
+        format!("{:.1}/{:.1}Wh", measurement.get("energy").unwrap_or(f64::NAN), measurement.get("energy_full").unwrap_or(f64::NAN))
+    },
+    pre_spaces: 0,
+    post_spaces: 2,
 };
 
 pub const BATTERY_POWER:StaticIconCommand = StaticIconCommand {
     icon: '',
-    call: |_| {
-        if let Some(battery) = get_battery() {
-            let energy_rate = battery.energy_rate();
-            let energy_rate_watts = energy_rate.get::<watt>();
-            let charge_indicator = match battery.state() {
-                battery::State::Charging => "+",
-                battery::State::Discharging => "-",
-                _ => "",
-            };
-
-            return Some(format!("{}{:.2}W", charge_indicator, energy_rate_watts));
-        }
-
-        None
+    measure: |_| {
+        let battery = get_battery()?;
+        let energy_rate = battery.energy_rate();
+        let direction = match battery.state() {
+            battery::State::Charging => 1.0,
+            battery::State::Discharging => -1.0,
+            _ => 0.0,
+        };
+
+        Ok(Measurement::new(&[
+            ("power", energy_rate.get::<watt>() as f64, Unit::Watts),
+            ("direction", direction, Unit::None),
+        ]))
+    },
+    format: |measurement, _| {
+        let charge_indicator = match measurement.get("direction") {
+            Some(direction) if direction > 0.0 => "+",
+            Some(direction) if direction < 0.0 => "-",
+            _ => "",
+        };
+
+        format!("{}{:.2}W", charge_indicator, measurement.get("power").unwrap_or(f64::NAN))
     },
     pre_spaces: 1,
     post_spaces: 3,
 };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    // unexpected behaviour, but shoudn't fail at least
-    #[test]
-    fn amount_format_negative() {
-        let formatted = format_amount(Decimal::new(-2368, 2));
-        assert_eq!(formatted, "-23.68");
-    }
-
-    #[test]
-    fn amount_format_zero() {
-        let formatted = format_amount(Decimal::from(0));
-        assert_eq!(formatted, "0");
-    }
-
-    #[test]
-    fn amount_format_tiny() {
-        let formatted = format_amount(Decimal::new(1, 3));
-        assert_eq!(formatted, "0.00");
-    }
-
-    #[test]
-    fn amount_format_almost_one() {
-        let formatted = format_amount(Decimal::new(996, 3));
-        assert_eq!(formatted, "0.99");
-    }
-
-    #[test]
-    fn amount_format_one() {
-        let formatted = format_amount(Decimal::from(1));
-        assert_eq!(formatted, "1");
-    }
-
-    #[test]
-    fn amount_format_almost_ten() {
-        let formatted = format_amount(Decimal::new(975, 2));
-        assert_eq!(formatted, "9.7");
-    }
-
-    #[test]
-    fn amount_format_ten() {
-        let formatted = format_amount(Decimal::from(10));
-        assert_eq!(formatted, "10");
-    }
-
-    #[test]
-    fn amount_format_ten_plus() {
-        let formatted = format_amount(Decimal::new(1000001, 5));
-        assert_eq!(formatted, "10.0");
-    }
-
-    #[test]
-    fn amount_format_99() {
-        let formatted = format_amount(Decimal::new(9995, 2));
-        assert_eq!(formatted, "99.9");
-    }
-
-    #[test]
-    fn amount_format_hundred() {
-        let formatted = format_amount(Decimal::from(100));
-        assert_eq!(formatted, "100");
-    }
-
-    #[test]
-    fn amount_format_alot() {
-        let formatted = format_amount(Decimal::new(123456, 2));
-        assert_eq!(formatted, "1234");
-    }
-
-    #[test]
-    fn amount_format_huge() {
-        let formatted = format_amount(Decimal::new(123749089, 2));
-        assert_eq!(formatted, "1237490");
-    }
-
-    #[test]
-    fn amount_format_near_boundary() {
-        let formatted = format_amount(Decimal::from(1073750016) / Decimal::from(1 << 30));
-        assert_eq!(formatted, "1.0");
-    }
-
-    #[test]
-    fn two_amounts_bytes() {
-        let formatted = format_two_amounts(3, 687, "/", true);
-        assert_eq!(formatted, "3/687B");
-    }
-
-    #[test]
-    fn two_amounts_zero() {
-        let formatted = format_two_amounts(0, 0, ":", true);
-        assert_eq!(formatted, "0:0B");
-    }
-
-    #[test]
-    fn two_amounts_zero_nobytes() {
-        let formatted = format_two_amounts(0, 0, ":", false);
-        assert_eq!(formatted, "0:0");
-    }
-
-    #[test]
-    fn two_amounts_zero_of_more() {
-        let formatted = format_two_amounts(0, 102938, "'", true);
-        assert_eq!(formatted, "0'100K");
-    }
-
-    #[test]
-    fn two_amounts_mega() {
-        let formatted = format_two_amounts(1232899, 2389999, "=", false);
-        assert_eq!(formatted, "1204=2333K");
-    }
+// forget the samples shared by commands, so the next run reads them again
+pub fn start_tick() {
+    RX_TX.lock().unwrap().clear();
+    *MEMINFO.lock().unwrap() = None;
+}
 
-    #[test]
-    fn two_amounts_mega_slight_asym() {
-        let formatted = format_two_amounts(1232899, 23899999, "⁚", false);
-        assert_eq!(formatted, "1.1⁚22.7M");
-    }
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub args: &'static [&'static str],
+    pub needs_root: bool,
+    pub command: Command,
+}
 
-    #[test]
-    fn two_amounts_mega_very_asym() {
-        let formatted = format_two_amounts(123289, 23899999, r"\", false);
-        assert_eq!(formatted, r"0.11\22.7M");
-    }
+pub static REGISTRY: &[CommandInfo] = &[
+    CommandInfo {
+        name: "loadavg",
+        description: "load average for 1 and 5 minutes",
+        args: &[],
+        needs_root: false,
+        command: Command::Static(LOADAVG),
+    },
+    CommandInfo {
+        name: "pressure",
+        description: "some:full stall percentages over 10 seconds of cpu, memory or io, of the system or of a cgroup v2 path",
+        args: &["resource", "cgroup"],
+        needs_root: false,
+        command: Command::Static(PRESSURE),
+    },
+    CommandInfo {
+        name: "cpu",
+        description: "usage and frequency level of every core (per-core), all of them (total), the N busiest (top-N) or a sparkline; nofreq hides the frequency",
+        args: &["mode", "freq"],
+        needs_root: false,
+        command: Command::Static(CPU),
+    },
+    CommandInfo {
+        name: "cpu_breakdown",
+        description: "user, system, iowait, steal and guest percentages of all the cores or of a cpuN",
+        args: &["cpu"],
+        needs_root: false,
+        command: Command::Static(CPU_BREAKDOWN),
+    },
+    CommandInfo {
+        name: "mem",
+        description: "used/total RAM",
+        args: &[],
+        needs_root: false,
+        command: Command::Static(MEM),
+    },
+    CommandInfo {
+        name: "zram",
+        description: "compressed:uncompressed swap size",
+        args: &[],
+        needs_root: false,
+        command: Command::Static(ZRAM),
+    },
+    CommandInfo {
+        name: "radeon_vram",
+        description: "used/total VRAM of the radeon driver",
+        args: &[],
+        needs_root: true,
+        command: Command::Static(RADEON_VRAM),
+    },
+    CommandInfo {
+        name: "gpu_mem",
+        description: "used/total VRAM and GTT of an amdgpu card, radeon needs root",
+        args: &["card"],
+        needs_root: false,
+        command: Command::Static(GPU_MEM),
+    },
+    CommandInfo {
+        name: "gpu_busy",
+        description: "amdgpu load or i915 frequency, with the clock level",
+        args: &["card"],
+        needs_root: false,
+        command: Command::Static(GPU_BUSY),
+    },
+    CommandInfo {
+        name: "traffic",
+        description: "received:transmitted bytes since the interface was up, \"all\" for all but loopback, \"auto\" for the default route",
+        args: &["interface"],
+        needs_root: false,
+        command: Command::Static(TRAFFIC),
+    },
+    CommandInfo {
+        name: "network_speed",
+        description: "received:transmitted bytes per second, \"all\" for all but loopback, \"auto\" for the default route",
+        args: &["interface"],
+        needs_root: false,
+        command: Command::Static(NETWORK_SPEED),
+    },
+    CommandInfo {
+        name: "radeon_temperature",
+        description: "radeon GPU temperature",
+        args: &[],
+        needs_root: false,
+        command: Command::Static(RADEON_TEMPERATURE),
+    },
+    CommandInfo {
+        name: "amd_k10_temperature",
+        description: "AMD K10 CPU temperature",
+        args: &[],
+        needs_root: false,
+        command: Command::Static(AMD_K10_TEMPERATURE),
+    },
+    CommandInfo {
+        name: "temperature",
+        description: "temperature of a chip (a libsensors name, e.g. coretemp-*) and an optional feature label, \"cpu\" or \"gpu\" to probe the known drivers",
+        args: &["chip", "feature"],
+        needs_root: false,
+        command: Command::Static(TEMPERATURE),
+    },
+    CommandInfo {
+        name: "fan",
+        description: "fan speed of a chip and an optional feature label",
+        args: &["chip", "feature"],
+        needs_root: false,
+        command: Command::Static(FAN),
+    },
+    CommandInfo {
+        name: "voltage",
+        description: "voltage of a chip and an optional feature label",
+        args: &["chip", "feature"],
+        needs_root: false,
+        command: Command::Static(VOLTAGE),
+    },
+    CommandInfo {
+        name: "power",
+        description: "power of a chip and an optional feature label",
+        args: &["chip", "feature"],
+        needs_root: false,
+        command: Command::Static(POWER),
+    },
+    CommandInfo {
+        name: "ata_hddtemp",
+        description: "disk temperature from S.M.A.R.T.",
+        args: &["device"],
+        needs_root: true,
+        command: Command::Static(ATA_HDDTEMP),
+    },
+    CommandInfo {
+        name: "ata_gsense_error_rate",
+        description: "G-sense error rate from S.M.A.R.T.",
+        args: &["device"],
+        needs_root: true,
+        command: Command::Static(ATA_GSENSE_ERROR_RATE),
+    },
+    CommandInfo {
+        name: "wireless_signal",
+        description: "wireless signal level in dBm, \"auto\" for the default route",
+        args: &["interface"],
+        needs_root: false,
+        command: Command::Static(WIRELESS_SIGNAL),
+    },
+    CommandInfo {
+        name: "disk_io_speed",
+        description: "read:written bytes per second",
+        args: &["disk"],
+        needs_root: false,
+        command: Command::Static(DISK_IO_SPEED),
+    },
+    CommandInfo {
+        name: "fs_free",
+        description: "free/total space of a filesystem",
+        args: &["mountpoint"],
+        needs_root: false,
+        command: Command::Static(FS_FREE),
+    },
+    CommandInfo {
+        name: "ups_voltage",
+        description: "input→output voltage of a NUT-managed UPS",
+        args: &["ups"],
+        needs_root: false,
+        command: Command::Static(UPS_VOLTAGE),
+    },
+    CommandInfo {
+        name: "battery",
+        description: "battery charge and energy",
+        args: &[],
+        needs_root: false,
+        command: Command::Dynamic(BATTERY),
+    },
+    CommandInfo {
+        name: "battery_power",
+        description: "battery charge/discharge rate",
+        args: &[],
+        needs_root: false,
+        command: Command::Static(BATTERY_POWER),
+    },
+];
 
-    #[test]
-    fn two_amounts_mega_extreme_asym() {
-        let formatted = format_two_amounts(123289, 23899999999, "O", true);
-        assert_eq!(formatted, "0.00O22.2G");
-    }
+pub fn find(name: &str) -> Option<&'static CommandInfo> {
+    REGISTRY.iter().find(|info| info.name == name)
+}
 
-    #[test]
-    fn two_amounts_first_larger() {
-        let formatted = format_two_amounts(23899999999, 123289, "lol", true);
-        assert_eq!(formatted, "22.2lol0.00G");
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::utils::TestDir;
 
     #[test]
     fn dbms_low() {
@@ -1192,4 +1858,335 @@ mod tests {
         let level = show_battery_icon(-23.0 as u8);
         assert_eq!(level, '');
     }
+
+    fn fake_traffic(rx: u64, tx: u64) -> MaybeTraffic {
+        Ok(Traffic { rx, tx })
+    }
+
+    const STAT: &str = "cpu  4705 150 1120 16250 520 0 31 7 0 0\n\
+        cpu0 2354 70 560 8125 260 0 20 3 0 0\n\
+        cpu1 2351 80 560 8125 260 0 11 4\n\
+        intr 114930548 113199788 3 0 5 263 0 4 [...]\n\
+        ctxt 1990473\n";
+
+    fn usage(name: &str, percentage: f64, freq_level: Option<f64>) -> CpuUsage {
+        CpuUsage { name: name.to_string(), percentage, freq_level }
+    }
+
+    #[test]
+    fn stat_lines() {
+        let stat = parse_stat(STAT);
+        assert_eq!(stat.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["cpu", "cpu0", "cpu1"]);
+        assert_eq!(stat[0].1.steal, 7);
+        assert_eq!(stat[0].1.guest, Some(0));
+        assert_eq!(stat[2].1.guest, None);
+        assert_eq!(stat[1].1.used(), 2354 + 70 + 560 + 20 + 3);
+        assert_eq!(stat[1].1.total(), stat[1].1.used() + 8125 + 260);
+    }
+
+    #[test]
+    fn cpu_modes() {
+        assert_eq!(parse_cpu_mode("top-4").unwrap(), CpuMode::Top(4));
+        assert_eq!(parse_cpu_mode("total").unwrap(), CpuMode::Total);
+        assert!(matches!(parse_cpu_mode("top-0"), Err(CommandError::Parse(_))));
+        assert!(matches!(parse_cpu_mode("all"), Err(CommandError::Parse(_))));
+    }
+
+    #[test]
+    fn cpu_top() {
+        let usages = vec![usage("cpu0", 10.0, None), usage("cpu1", f64::NAN, None), usage("cpu2", 90.0, Some(80.0)), usage("cpu3", 40.0, None)];
+        let measurement = cpu_measurement(usages, &CpuMode::Top(2));
+        assert_eq!((CPU.format)(&measurement, &["top-2"]), "⁝90% 40%");
+    }
+
+    #[test]
+    fn cpu_total() {
+        let measurement = cpu_measurement(vec![usage("cpu", 25.0, Some(10.0))], &CpuMode::Total);
+        assert_eq!(measurement.bar(), Some(25));
+        assert_eq!((CPU.format)(&measurement, &["total"]), "·25%");
+    }
+
+    #[test]
+    fn cpu_sparkline() {
+        let usages = vec![usage("cpu0", 0.0, Some(90.0)), usage("cpu1", 50.0, Some(50.0)), usage("cpu2", 100.0, Some(70.0)), usage("cpu3", f64::NAN, None)];
+        let measurement = cpu_measurement(usages, &CpuMode::Sparkline);
+        assert_eq!((CPU.format)(&measurement, &["sparkline"]), "⁝▁▅█ ");
+        assert_eq!((CPU.format)(&measurement, &["sparkline", "freq"]), "⁝▁▅█ ");
+
+        let usages = vec![usage("cpu0", 0.0, None), usage("cpu1", 50.0, None)];
+        let measurement = cpu_measurement(usages, &CpuMode::Sparkline);
+        assert_eq!((CPU.format)(&measurement, &["sparkline"]), "▁▅");
+        // the measurement itself isn't tied to the rendering
+        assert_eq!((CPU.format)(&measurement, &[]), "0% 50%");
+    }
+
+    #[test]
+    fn breakdown_guest_out_of_user() {
+        let stat = parse_stat("cpu  100 20 30 1000 40 5 5 10 60 0\n");
+        assert_eq!(breakdown_counters(&stat[0].1), vec![1210, 60, 40, 40, 10, 60]);
+
+        // an old kernel without the guest time
+        let stat = parse_stat("cpu  100 20 30 1000 40 5 5 10\n");
+        assert_eq!(breakdown_counters(&stat[0].1), vec![1210, 120, 40, 40, 10]);
+    }
+
+    #[test]
+    fn breakdown_percentages() {
+        let measurement = breakdown_measurement(&[Some(200), Some(30), Some(10), Some(5), Some(15), Some(20)]);
+        assert_eq!(measurement.get("user"), Some(15.0));
+        assert_eq!(measurement.get("steal"), Some(7.5));
+        assert_eq!(measurement.get("guest"), Some(10.0));
+        assert_eq!((CPU_BREAKDOWN.format)(&measurement, &[]), "us15% sy5% wa2% st8% gu10%");
+    }
+
+    #[test]
+    fn breakdown_reset() {
+        let measurement = breakdown_measurement(&[Some(200), Some(30), None, Some(5), Some(15)]);
+        assert_eq!(measurement.get("guest"), None);
+        assert_eq!((CPU_BREAKDOWN.format)(&measurement, &[]), "us15% sy? wa2% st8%");
+
+        let measurement = breakdown_measurement(&[None; 5]);
+        assert_eq!((CPU_BREAKDOWN.format)(&measurement, &[]), "us? sy? wa? st?");
+    }
+
+    #[test]
+    fn pressure_some_and_full() {
+        let measurement = parse_pressure("some avg10=12.34 avg60=5.00 avg300=1.00 total=123456\nfull avg10=0.05 avg60=0.00 avg300=0.00 total=789\n").unwrap();
+        assert_eq!(measurement.get("some"), Some(12.34));
+        assert_eq!(measurement.bar(), Some(12));
+        assert_eq!((PRESSURE.format)(&measurement, &[]), "12.3:0.1%");
+    }
+
+    #[test]
+    fn pressure_some_only() {
+        let measurement = parse_pressure("some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").unwrap();
+        assert_eq!(measurement.get("full"), None);
+        assert_eq!((PRESSURE.format)(&measurement, &[]), "0.0%");
+        assert!(matches!(parse_pressure("some total=0\n"), Err(CommandError::Parse(_))));
+    }
+
+    #[test]
+    fn pressure_files() {
+        assert_eq!(pressure_file("io", None).unwrap(), path::Path::new("/proc/pressure/io"));
+        assert_eq!(
+            pressure_file("memory", Some("user.slice/user-1000.slice")).unwrap(),
+            path::Path::new("/sys/fs/cgroup/user.slice/user-1000.slice/memory.pressure"),
+        );
+        assert!(matches!(pressure_file("memory", Some("/user.slice")), Err(CommandError::Parse(_))));
+        assert!(matches!(pressure_file("memory", Some("user.slice/../../../etc")), Err(CommandError::Parse(_))));
+        assert!(matches!(pressure_file("irq", None), Err(CommandError::Parse(_))));
+    }
+
+    #[test]
+    fn temperature_aliases() {
+        assert_eq!(temperature_probes("cpu", None).unwrap()[0], ("coretemp-*", Some("Package id 0")));
+        assert_eq!(temperature_probes("gpu", None).unwrap()[0], ("amdgpu-*", Some("edge")));
+        assert_eq!(temperature_probes("nct6775-*", Some("SYSTIN")).unwrap(), vec![("nct6775-*", Some("SYSTIN"))]);
+    }
+
+    #[test]
+    fn temperature_alias_with_feature() {
+        assert!(matches!(temperature_probes("gpu", Some("junction")), Err(CommandError::Parse(_))));
+    }
+
+    #[test]
+    fn sensor_within_limits() {
+        assert_eq!(sensor_severity(1200.0, Some(600.0), None, &[0.0, 0.0]), Severity::Normal);
+        // unset limits
+        assert_eq!(sensor_severity(1.2, Some(0.0), Some(0.0), &[]), Severity::Normal);
+    }
+
+    #[test]
+    fn sensor_out_of_limits() {
+        assert_eq!(sensor_severity(300.0, Some(600.0), None, &[]), Severity::Warning);
+        assert_eq!(sensor_severity(12.9, Some(11.4), Some(12.6), &[]), Severity::Warning);
+    }
+
+    #[test]
+    fn sensor_alarm() {
+        assert_eq!(sensor_severity(1.2, Some(1.0), Some(1.4), &[0.0, 1.0]), Severity::Warning);
+    }
+
+    #[test]
+    fn gpu_mem_amdgpu() {
+        let drm_dir = TestDir::new("drm");
+        drm_dir.write("card1/device/mem_info_vram_used", "1073741824\n");
+        drm_dir.write("card1/device/mem_info_vram_total", "8589934592\n");
+        drm_dir.write("card1/device/mem_info_gtt_used", "52428800\n");
+        drm_dir.write("card1/device/mem_info_gtt_total", "17179869184\n");
+
+        let measurement = read_gpu_mem(&drm_dir, &TestDir::new("no-debugfs"), 1).unwrap();
+        assert_eq!(measurement.get("used"), Some(1073741824.0));
+        assert_eq!(measurement.get("gtt_total"), Some(17179869184.0));
+        assert_eq!(measurement.bar(), Some(12));
+        assert_eq!((GPU_MEM.format)(&measurement, &[]), "1024/8192M 0.04/16G");
+    }
+
+    #[test]
+    fn gpu_mem_radeon() {
+        let debug_dri_dir = TestDir::new("dri");
+        debug_dri_dir.write(
+            "0/radeon_vram_mm",
+            "0x00000000-0x00000100: 256: used\n0x00000100-0x00040000: 261888: free\ntotal: 262144, used 65536 free 196608\n",
+        );
+
+        // no amdgpu files, the debugfs one is read
+        let measurement = read_gpu_mem(&TestDir::new("no-drm"), &debug_dri_dir, 0).unwrap();
+        assert_eq!(measurement.get("used"), Some(268435456.0));
+        assert_eq!(measurement.get("total"), Some(1073741824.0));
+        assert_eq!(measurement.get("gtt_used"), None);
+        assert_eq!((GPU_MEM.format)(&measurement, &[]), "256/1024M");
+    }
+
+    #[test]
+    fn gpu_busy_amdgpu() {
+        let drm_dir = TestDir::new("drm-busy");
+        drm_dir.write("card0/device/gpu_busy_percent", "87\n");
+        drm_dir.write("card0/device/pp_dpm_sclk", "0: 500Mhz \n1: 1300Mhz *\n2: 2100Mhz \n");
+
+        let measurement = read_gpu_busy(&drm_dir, 0).unwrap();
+        assert_eq!(measurement.get("busy"), Some(87.0));
+        assert_eq!(measurement.get("freq"), Some(50.0));
+        assert_eq!(measurement.get("mhz"), Some(1300.0));
+        assert_eq!((GPU_BUSY.format)(&measurement, &[]), "⁚87%");
+    }
+
+    #[test]
+    fn gpu_busy_i915() {
+        let drm_dir = TestDir::new("drm-i915");
+        drm_dir.write("card0/gt_cur_freq_mhz", "1100\n");
+        drm_dir.write("card0/gt_min_freq_mhz", "300\n");
+        drm_dir.write("card0/gt_max_freq_mhz", "1300\n");
+
+        let measurement = read_gpu_busy(&drm_dir, 0).unwrap();
+        assert_eq!(measurement.get("busy"), None);
+        assert_eq!(measurement.get("freq"), Some(80.0));
+        assert_eq!((GPU_BUSY.format)(&measurement, &[]), "⁝1100MHz");
+    }
+
+    #[test]
+    fn dpm_level_no_active() {
+        assert_eq!(parse_dpm_level("0: 500Mhz \n1: 1300Mhz \n"), None);
+        assert_eq!(parse_dpm_level("0: 500Mhz *\n"), Some((0.0, 500)));
+    }
+
+    #[test]
+    fn radeon_vram_mm_garbage() {
+        assert!(matches!(parse_radeon_vram_mm("total: 262144"), Err(CommandError::Parse(_))));
+        assert!(matches!(parse_radeon_vram_mm(""), Err(CommandError::Parse(_))));
+    }
+
+    #[test]
+    fn default_route_lowest_metric() {
+        let route = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            eth0\t0000A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n\
+            wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n\
+            eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n";
+        assert_eq!(default_route_iface(route), Some("eth0".to_string()));
+    }
+
+    #[test]
+    fn no_default_route() {
+        let route = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            eth0\t0000A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n";
+        assert_eq!(default_route_iface(route), None);
+    }
+
+    #[test]
+    fn first_up_but_loopback() {
+        let net_dir = TestDir::new("up");
+        fake_iface(&net_dir, "lo", ARPHRD_LOOPBACK, 0, 0);
+        fake_iface(&net_dir, "eth0", "1", 0, 0);
+        fake_iface(&net_dir, "wlan0", "1", 0, 0);
+        net_dir.write("lo/operstate", "unknown\n");
+        net_dir.write("eth0/operstate", "down\n");
+        net_dir.write("wlan0/operstate", "up\n");
+
+        assert_eq!(first_up_iface(&net_dir), Some("wlan0".to_string()));
+    }
+
+    fn fake_iface(net_dir: &TestDir, iface: &str, iface_type: &str, rx: u64, tx: u64) {
+        net_dir.write(&format!("{}/type", iface), &(iface_type.to_string() + "\n"));
+        net_dir.write(&format!("{}/statistics/rx_bytes", iface), &(rx.to_string() + "\n"));
+        net_dir.write(&format!("{}/statistics/tx_bytes", iface), &(tx.to_string() + "\n"));
+    }
+
+    #[test]
+    fn traffic_all_but_loopback() {
+        let net_dir = TestDir::new("net");
+        fake_iface(&net_dir, "lo", ARPHRD_LOOPBACK, 100000, 100000);
+        fake_iface(&net_dir, "eth0", "1", 1000, 2000);
+        fake_iface(&net_dir, "wlan0", "1", 30, 40);
+
+        let eth0 = fetch_traffic(&net_dir, "eth0").unwrap();
+        assert_eq!((eth0.rx, eth0.tx), (1000, 2000));
+        let all = fetch_all_traffic(&net_dir).unwrap();
+        assert_eq!((all.rx, all.tx), (1030, 2040));
+        assert!(matches!(fetch_traffic(&net_dir, "ppp0"), Err(CommandError::NotAvailable(_))));
+    }
+
+    #[test]
+    fn network_speed_two_interfaces() {
+        state::keep_in_memory();
+
+        assert!(network_speed("test-wlan0", "test-wlan0", fake_traffic(1000, 1000)).is_err());
+        assert!(network_speed("test-eth0", "test-eth0", fake_traffic(50000, 50000)).is_err());
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+
+        let wlan0 = network_speed("test-wlan0", "test-wlan0", fake_traffic(2000, 1000)).unwrap();
+        let eth0 = network_speed("test-eth0", "test-eth0", fake_traffic(50000, 50000)).unwrap();
+        assert!(wlan0.get("rx").unwrap() > 0.0);
+        assert_eq!(wlan0.get("tx"), Some(0.0));
+        assert_eq!(eth0.get("rx"), Some(0.0));
+        assert_eq!(eth0.get("tx"), Some(0.0));
+    }
+
+    #[test]
+    fn disk_io_speed_wrap_32() {
+        state::keep_in_memory();
+        let max = u32::MAX as u64;
+
+        assert!(disk_io_speed("test-sda", max - 9, 100, max).is_err());
+
+        std::thread::sleep(std::time::Duration::from_millis(1));
+
+        let measurement = disk_io_speed("test-sda", 10, 100, max).unwrap();
+        let read = measurement.get("read").unwrap();
+        assert!(read > 0.0 && read.is_finite());
+        assert_eq!(measurement.get("written"), Some(0.0));
+    }
+
+    #[test]
+    fn network_speed_auto_and_name() {
+        state::keep_in_memory();
+
+        assert!(network_speed("auto", "test-wlan1", fake_traffic(1000, 1000)).is_err());
+        assert!(network_speed("test-wlan1", "test-wlan1", fake_traffic(1000, 1000)).is_err());
+    }
+
+    #[test]
+    fn network_speed_disconnected() {
+        let measurement = network_speed("test-gone0", "test-gone0", Err(CommandError::NotAvailable("gone".to_string()))).unwrap();
+        assert_eq!(measurement.message, Some(DISCONNECTED.to_string()));
+    }
+
+    #[test]
+    fn registry_names_unique() {
+        for (i, info) in REGISTRY.iter().enumerate() {
+            assert!(REGISTRY[i + 1..].iter().all(|other| other.name != info.name), "{} is registered twice", info.name);
+        }
+    }
+
+    #[test]
+    fn registry_find() {
+        let info = find("ata_hddtemp").expect("ata_hddtemp is not registered");
+        assert_eq!(info.args, &["device"]);
+        assert!(info.needs_root);
+    }
+
+    #[test]
+    fn registry_find_unknown() {
+        assert!(find("LOADAVG").is_none());
+    }
 }
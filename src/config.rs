@@ -0,0 +1,301 @@
+extern crate serde;
+extern crate toml;
+
+use std::env;
+use std::fs;
+use std::path;
+use std::time;
+
+use serde::Deserialize;
+
+use super::commands;
+
+const CONFIG_DIR: &str = "limon";
+const CONFIG_FILE: &str = "config.toml";
+
+// long enough for SMART and NUT, short enough for a panel tick
+const DEFAULT_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+// used when there's no config file: the original list, with the generic gpu,
+// temperature and auto interface commands in place of the radeon, k10 and wlan0 ones
+const DEFAULT_CONFIG: &str = r#"
+[[command]]
+name = "loadavg"
+
+[[command]]
+name = "cpu"
+
+[[command]]
+name = "mem"
+
+[[command]]
+name = "zram"
+
+[[command]]
+name = "gpu_mem"
+args = ["0"]
+interval = 10
+
+[[command]]
+name = "traffic"
+args = ["auto"]
+
+[[command]]
+name = "temperature"
+args = ["gpu"]
+
+[[command]]
+name = "temperature"
+args = ["cpu"]
+
+[[command]]
+name = "ata_hddtemp"
+args = ["/dev/sda"]
+interval = 300
+
+[[command]]
+name = "ata_gsense_error_rate"
+args = ["/dev/sda"]
+interval = 300
+
+[[command]]
+name = "network_speed"
+args = ["auto"]
+
+[[command]]
+name = "wireless_signal"
+args = ["auto"]
+
+[[command]]
+name = "disk_io_speed"
+args = ["sda"]
+
+[[command]]
+name = "fs_free"
+args = ["/"]
+
+[[command]]
+name = "ups_voltage"
+args = ["nutdev"]
+interval = 30
+
+[[command]]
+name = "battery"
+"#;
+
+#[derive(Deserialize)]
+pub struct CommandEntry {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    // seconds to reuse the last good result for, every run if absent
+    pub interval: Option<f64>,
+    // seconds to wait for the result before giving up on it
+    pub timeout: Option<f64>,
+}
+
+impl CommandEntry {
+    pub fn refresh(&self) -> Option<time::Duration> {
+        self.interval.map(time::Duration::from_secs_f64)
+    }
+
+    pub fn timeout(&self) -> time::Duration {
+        self.timeout.map(time::Duration::from_secs_f64).unwrap_or(DEFAULT_TIMEOUT)
+    }
+}
+
+impl CommandEntry {
+    // name[:arg1[:arg2...]], e.g. traffic:eth0 or fs_free:/home
+    pub fn from_spec(spec: &str) -> Result<CommandEntry, String> {
+        let mut parts = spec.split(':');
+        let name = parts.next().unwrap_or("").to_string();
+
+        if commands::find(&name).is_none() {
+            return Err(format!("unknown command \"{}\" in \"{}\"", name, spec));
+        }
+
+        Ok(CommandEntry {
+            name,
+            args: parts.map(String::from).collect(),
+            interval: None,
+            timeout: None,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    #[serde(rename = "command", default)]
+    pub commands: Vec<CommandEntry>,
+}
+
+// $XDG_CONFIG_HOME/limon/config.toml, or ~/.config/limon/config.toml
+pub fn default_path() -> Option<path::PathBuf> {
+    let mut config_path = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => path::PathBuf::from(dir),
+        _ => {
+            let mut home = path::PathBuf::from(env::var_os("HOME")?);
+            home.push(".config");
+            home
+        }
+    };
+
+    config_path.push(CONFIG_DIR);
+    config_path.push(CONFIG_FILE);
+
+    Some(config_path)
+}
+
+// a positive number of seconds a Duration can hold, 1e20 or inf can't be slept for
+pub fn seconds(seconds: f64) -> Result<time::Duration, String> {
+    if seconds.is_nan() || seconds <= 0.0 {
+        return Err("must be positive".to_string());
+    }
+
+    time::Duration::try_from_secs_f64(seconds).map_err(|_| "is too large".to_string())
+}
+
+pub fn parse(contents: &str) -> Result<Config, String> {
+    let config: Config = toml::from_str(contents).map_err(|e| e.to_string())?;
+
+    for (i, entry) in config.commands.iter().enumerate() {
+        if commands::find(&entry.name).is_none() {
+            return Err(format!("unknown command \"{}\" in entry #{}", entry.name, i + 1));
+        }
+        for (key, value) in &[("interval", entry.interval), ("timeout", entry.timeout)] {
+            if let Some(Err(e)) = value.map(seconds) {
+                return Err(format!("{} of entry #{} {}", key, i + 1, e));
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+pub fn from_specs(specs: &[String]) -> Result<Config, String> {
+    Ok(Config {
+        commands: specs.iter().map(|spec| CommandEntry::from_spec(spec)).collect::<Result<_, _>>()?,
+    })
+}
+
+pub fn load(config_path: &path::Path) -> Result<Config, String> {
+    let contents = fs::read_to_string(config_path)
+        .map_err(|e| format!("can't read {}: {}", config_path.display(), e))?;
+
+    parse(&contents).map_err(|e| format!("{}: {}", config_path.display(), e))
+}
+
+// an explicitly passed config must exist, the default one may be absent
+pub fn load_or_default(config_path: Option<&path::Path>) -> Result<Config, String> {
+    match config_path {
+        Some(config_path) => load(config_path),
+        None => match default_path() {
+            Some(config_path) if config_path.exists() => load(&config_path),
+            _ => parse(DEFAULT_CONFIG),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        let config = parse(DEFAULT_CONFIG).expect("Default config is broken");
+        assert_eq!(config.commands.len(), 16);
+    }
+
+    #[test]
+    fn config_keeps_order_and_args() {
+        let config = parse("[[command]]\nname = \"fs_free\"\nargs = [\"/home\"]\n\n[[command]]\nname = \"cpu\"\n").unwrap();
+        assert_eq!(config.commands[0].name, "fs_free");
+        assert_eq!(config.commands[0].args, vec!["/home".to_string()]);
+        assert_eq!(config.commands[1].name, "cpu");
+        assert!(config.commands[1].args.is_empty());
+    }
+
+    #[test]
+    fn config_empty() {
+        let config = parse("").unwrap();
+        assert!(config.commands.is_empty());
+    }
+
+    #[test]
+    fn config_unknown_command() {
+        let err = parse("[[command]]\nname = \"cpu\"\n\n[[command]]\nname = \"gpu_fan\"\n").err().unwrap();
+        assert_eq!(err, "unknown command \"gpu_fan\" in entry #2");
+    }
+
+    #[test]
+    fn spec_no_args() {
+        let entry = CommandEntry::from_spec("cpu").unwrap();
+        assert_eq!(entry.name, "cpu");
+        assert!(entry.args.is_empty());
+    }
+
+    #[test]
+    fn spec_with_args() {
+        let entry = CommandEntry::from_spec("fs_free:/home").unwrap();
+        assert_eq!(entry.name, "fs_free");
+        assert_eq!(entry.args, vec!["/home".to_string()]);
+    }
+
+    #[test]
+    fn spec_empty_arg() {
+        let entry = CommandEntry::from_spec("traffic:").unwrap();
+        assert_eq!(entry.args, vec!["".to_string()]);
+    }
+
+    #[test]
+    fn spec_unknown() {
+        let err = CommandEntry::from_spec("nosuch:1").err().unwrap();
+        assert_eq!(err, "unknown command \"nosuch\" in \"nosuch:1\"");
+    }
+
+    #[test]
+    fn specs_keep_order() {
+        let config = from_specs(&["mem".to_string(), "traffic:eth0".to_string(), "cpu".to_string()]).unwrap();
+        let names: Vec<&str> = config.commands.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["mem", "traffic", "cpu"]);
+    }
+
+    #[test]
+    fn config_interval() {
+        let config = parse("[[command]]\nname = \"ata_hddtemp\"\nargs = [\"/dev/sda\"]\ninterval = 300\n\n[[command]]\nname = \"cpu\"\n").unwrap();
+        assert_eq!(config.commands[0].refresh(), Some(time::Duration::from_secs(300)));
+        assert_eq!(config.commands[1].refresh(), None);
+    }
+
+    #[test]
+    fn config_interval_not_positive() {
+        let err = parse("[[command]]\nname = \"cpu\"\ninterval = 0\n").err().unwrap();
+        assert_eq!(err, "interval of entry #1 must be positive");
+    }
+
+    #[test]
+    fn config_timeout() {
+        let config = parse("[[command]]\nname = \"ups_voltage\"\nargs = [\"nutdev\"]\ntimeout = 0.5\n\n[[command]]\nname = \"cpu\"\n").unwrap();
+        assert_eq!(config.commands[0].timeout(), time::Duration::from_millis(500));
+        assert_eq!(config.commands[1].timeout(), DEFAULT_TIMEOUT);
+    }
+
+    #[test]
+    fn config_timeout_not_positive() {
+        let err = parse("[[command]]\nname = \"cpu\"\ntimeout = -1\n").err().unwrap();
+        assert_eq!(err, "timeout of entry #1 must be positive");
+    }
+
+    #[test]
+    fn config_seconds_too_large() {
+        let err = parse("[[command]]\nname = \"cpu\"\ninterval = 1e20\n").err().unwrap();
+        assert_eq!(err, "interval of entry #1 is too large");
+        let err = parse("[[command]]\nname = \"cpu\"\ntimeout = inf\n").err().unwrap();
+        assert_eq!(err, "timeout of entry #1 is too large");
+    }
+
+    #[test]
+    fn config_malformed() {
+        assert!(parse("[[command]]\nargs = [\"wlan0\"]\n").is_err());
+    }
+}
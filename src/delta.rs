@@ -0,0 +1,199 @@
+extern crate lazy_static;
+
+use std::fs;
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use itertools::free::join;
+
+use super::error::CommandError;
+use super::state;
+use super::utils::{trim_trailing_newline, boottime_ns};
+
+// the boot time clock and all the kernel counters start over on boot
+lazy_static! {
+    static ref BOOT_ID: String = {
+        match fs::read_to_string("/proc/sys/kernel/random/boot_id") {
+            Ok(mut boot_id) => {
+                trim_trailing_newline(&mut boot_id);
+                boot_id
+            },
+            Err(_) => "-".to_string(),
+        }
+    };
+}
+
+// counters the kernel keeps as unsigned long
+pub const ULONG_MAX: u64 = usize::MAX as u64;
+
+#[derive(Debug, PartialEq)]
+pub struct Sample {
+    boot_id: String,
+    // nanoseconds since boot
+    time: u64,
+    counters: Vec<u64>,
+}
+
+impl Sample {
+    pub fn now(counters: &[u64]) -> Sample {
+        Sample {
+            boot_id: BOOT_ID.clone(),
+            time: boottime_ns(),
+            counters: counters.to_vec(),
+        }
+    }
+
+    // "boot_id time counter..."
+    fn parse(state: &str) -> Option<Sample> {
+        let mut fields = state.split(' ');
+
+        let boot_id = fields.next()?.to_string();
+        let time = u64::from_str(fields.next()?).ok()?;
+        let counters = fields.map(u64::from_str).collect::<Result<Vec<u64>, _>>().ok()?;
+
+        Some(Sample { boot_id, time, counters })
+    }
+
+    fn to_state(&self) -> String {
+        format!("{} {} {}", self.boot_id, self.time, join(self.counters.iter(), " "))
+    }
+}
+
+pub struct Delta {
+    pub seconds: f64,
+    // None for the counters that were reset
+    pub counters: Vec<Option<u64>>,
+}
+
+impl Delta {
+    // NaN for the reset counters, it's shown as "?"
+    pub fn rates(&self) -> Vec<f64> {
+        self.counters.iter().map(|delta| match delta {
+            Some(delta) => *delta as f64 / self.seconds,
+            None => f64::NAN,
+        }).collect()
+    }
+}
+
+fn counter_delta(old: u64, new: u64, max: u64) -> Option<u64> {
+    if new >= old {
+        return Some(new - old);
+    }
+
+    // a counter that went past max starts from zero, but a wrap can't
+    // be told from a reset when it's more than a half of the range
+    let wrapped = (max - old).checked_add(new)?.checked_add(1)?;
+    if wrapped <= max / 2 {
+        Some(wrapped)
+    } else {
+        None
+    }
+}
+
+// None if the samples can't be compared at all, e.g. taken on different boots
+pub fn delta(old: &Sample, new: &Sample, max: u64) -> Option<Delta> {
+    if old.boot_id != new.boot_id || old.time >= new.time || old.counters.len() != new.counters.len() {
+        return None;
+    }
+
+    Some(Delta {
+        seconds: (new.time - old.time) as f64 / 1e9,
+        counters: old.counters.iter().zip(&new.counters).map(|(old, new)| counter_delta(*old, *new, max)).collect(),
+    })
+}
+
+// an empty state means the first run, there's nothing to compare with yet
+pub fn no_previous_sample() -> CommandError {
+    CommandError::Transient("no previous sample yet".to_string())
+}
+
+// saves the counters and compares them with the previously saved ones
+pub fn track(name: &str, counters: &[u64], max: u64) -> Result<Option<Delta>, CommandError> {
+    let new = Sample::now(counters);
+
+    // save anyway, display only if there was an old state
+    let old_state = state::persist(name, &new.to_state())?;
+    let old = Sample::parse(&old_state).ok_or_else(no_previous_sample)?;
+
+    Ok(delta(&old, &new, max))
+}
+
+// per second change of the counters, NaN for one tick after a reset
+pub fn rates(name: &str, counters: &[u64], max: u64) -> Result<Vec<f64>, CommandError> {
+    Ok(match track(name, counters, max)? {
+        Some(delta) => delta.rates(),
+        None => vec![f64::NAN; counters.len()],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(boot_id: &str, time: u64, counters: &[u64]) -> Sample {
+        Sample { boot_id: boot_id.to_string(), time, counters: counters.to_vec() }
+    }
+
+    #[test]
+    fn state_roundtrip() {
+        let s = sample("4f1d", 123, &[1, 2]);
+        assert_eq!(s.to_state(), "4f1d 123 1 2");
+        assert_eq!(Sample::parse(&s.to_state()), Some(s));
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(Sample::parse(""), None);
+    }
+
+    #[test]
+    fn parse_garbage() {
+        assert_eq!(Sample::parse("4f1d 123 1 x"), None);
+    }
+
+    #[test]
+    fn rates_per_second() {
+        let delta = delta(&sample("a", 1_000_000_000, &[1000, 5000]), &sample("a", 3_000_000_000, &[3000, 5000]), u64::MAX).unwrap();
+        assert_eq!(delta.rates(), vec![1000.0, 0.0]);
+    }
+
+    #[test]
+    fn other_boot() {
+        assert!(delta(&sample("a", 1, &[1000]), &sample("b", 2, &[2000]), u64::MAX).is_none());
+    }
+
+    #[test]
+    fn time_went_back() {
+        assert!(delta(&sample("a", 2, &[1000]), &sample("a", 1, &[2000]), u64::MAX).is_none());
+    }
+
+    #[test]
+    fn other_counters() {
+        assert!(delta(&sample("a", 1, &[1000]), &sample("a", 2, &[2000, 3000]), u64::MAX).is_none());
+    }
+
+    #[test]
+    fn counter_reset() {
+        let delta = delta(&sample("a", 1_000_000_000, &[1000, 5000]), &sample("a", 2_000_000_000, &[10, 6000]), u64::MAX).unwrap();
+        let rates = delta.rates();
+        assert!(rates[0].is_nan());
+        assert_eq!(rates[1], 1000.0);
+    }
+
+    #[test]
+    fn counter_wrap_32() {
+        let max = u32::MAX as u64;
+        assert_eq!(counter_delta(max - 9, 10, max), Some(20));
+    }
+
+    #[test]
+    fn counter_wrap_64() {
+        assert_eq!(counter_delta(u64::MAX, 0, u64::MAX), Some(1));
+    }
+
+    #[test]
+    fn counter_reset_32() {
+        let max = u32::MAX as u64;
+        assert_eq!(counter_delta(1000, 10, max), None);
+    }
+}
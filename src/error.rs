@@ -0,0 +1,184 @@
+extern crate serde;
+extern crate procfs;
+extern crate hdd;
+extern crate battery;
+extern crate sensors;
+
+use std::fmt;
+use std::io;
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum CommandError {
+    // the hardware, driver or file isn't there
+    NotAvailable(String),
+    // usually fixed by running as root
+    PermissionDenied(String),
+    // unexpected data or arguments
+    Parse(String),
+    // may go away on the next run
+    Transient(String),
+}
+
+impl CommandError {
+    // a short placeholder for the places where the value should be
+    pub fn placeholder(&self) -> &'static str {
+        match self {
+            CommandError::NotAvailable(_) => "#N/A#",
+            CommandError::PermissionDenied(_) => "#EPERM#",
+            CommandError::Parse(_) => "#PARSE#",
+            CommandError::Transient(_) => "#AGAIN#",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            CommandError::NotAvailable(message) |
+            CommandError::PermissionDenied(message) |
+            CommandError::Parse(message) |
+            CommandError::Transient(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self {
+            CommandError::NotAvailable(_) => "not available",
+            CommandError::PermissionDenied(_) => "permission denied",
+            CommandError::Parse(_) => "parse failure",
+            CommandError::Transient(_) => "transient failure",
+        };
+
+        write!(f, "{}: {}", kind, self.message())
+    }
+}
+
+impl From<io::Error> for CommandError {
+    fn from(e: io::Error) -> CommandError {
+        match e.kind() {
+            io::ErrorKind::NotFound => CommandError::NotAvailable(e.to_string()),
+            io::ErrorKind::PermissionDenied => CommandError::PermissionDenied(e.to_string()),
+            io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => CommandError::Parse(e.to_string()),
+            _ => CommandError::Transient(e.to_string()),
+        }
+    }
+}
+
+impl From<&procfs::ProcError> for CommandError {
+    fn from(e: &procfs::ProcError) -> CommandError {
+        match e {
+            procfs::ProcError::PermissionDenied(_) => CommandError::PermissionDenied(e.to_string()),
+            procfs::ProcError::NotFound(_) => CommandError::NotAvailable(e.to_string()),
+            procfs::ProcError::Incomplete(_) => CommandError::Transient(e.to_string()),
+            procfs::ProcError::Io(io_error, _) => CommandError::from(io::Error::new(io_error.kind(), e.to_string())),
+            _ => CommandError::Parse(e.to_string()),
+        }
+    }
+}
+
+impl From<procfs::ProcError> for CommandError {
+    fn from(e: procfs::ProcError) -> CommandError {
+        CommandError::from(&e)
+    }
+}
+
+impl From<hdd::ata::misc::Error> for CommandError {
+    fn from(e: hdd::ata::misc::Error) -> CommandError {
+        match e {
+            hdd::ata::misc::Error::IO(io_error) => CommandError::from(io_error),
+            _ => CommandError::Transient(e.to_string()),
+        }
+    }
+}
+
+impl From<rups::ClientError> for CommandError {
+    fn from(e: rups::ClientError) -> CommandError {
+        match e {
+            rups::ClientError::Io(io_error) => CommandError::from(io_error),
+            rups::ClientError::Nut(rups::NutError::AccessDenied) => CommandError::PermissionDenied(e.to_string()),
+            rups::ClientError::Nut(rups::NutError::UnknownUps) |
+            rups::ClientError::Nut(rups::NutError::VarNotSupported) => CommandError::NotAvailable(e.to_string()),
+            _ => CommandError::Transient(e.to_string()),
+        }
+    }
+}
+
+impl From<battery::Error> for CommandError {
+    fn from(e: battery::Error) -> CommandError {
+        // the crate wraps an io::Error, its kind says what happened
+        let kind = std::error::Error::source(&e)
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .map(io::Error::kind)
+            .unwrap_or(io::ErrorKind::Other);
+
+        CommandError::from(io::Error::new(kind, e.to_string()))
+    }
+}
+
+impl From<sensors::LibsensorsError> for CommandError {
+    fn from(e: sensors::LibsensorsError) -> CommandError {
+        CommandError::Transient(e.to_string())
+    }
+}
+
+impl From<std::ffi::NulError> for CommandError {
+    fn from(e: std::ffi::NulError) -> CommandError {
+        CommandError::Parse(e.to_string())
+    }
+}
+
+impl From<std::num::ParseIntError> for CommandError {
+    fn from(e: std::num::ParseIntError) -> CommandError {
+        CommandError::Parse(e.to_string())
+    }
+}
+
+impl From<std::num::ParseFloatError> for CommandError {
+    fn from(e: std::num::ParseFloatError) -> CommandError {
+        CommandError::Parse(e.to_string())
+    }
+}
+
+impl From<rust_decimal::Error> for CommandError {
+    fn from(e: rust_decimal::Error) -> CommandError {
+        CommandError::Parse(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_not_found() {
+        let e = CommandError::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        assert_eq!(e, CommandError::NotAvailable("gone".to_string()));
+    }
+
+    #[test]
+    fn io_permission_denied() {
+        let e = CommandError::from(io::Error::new(io::ErrorKind::PermissionDenied, "root only"));
+        assert_eq!(e.placeholder(), "#EPERM#");
+    }
+
+    #[test]
+    fn io_other_is_transient() {
+        let e = CommandError::from(io::Error::new(io::ErrorKind::TimedOut, "slow"));
+        assert_eq!(e, CommandError::Transient("slow".to_string()));
+    }
+
+    #[test]
+    fn parse_int() {
+        let e = CommandError::from("x".parse::<u64>().unwrap_err());
+        assert_eq!(e.placeholder(), "#PARSE#");
+    }
+
+    #[test]
+    fn display_has_kind_and_cause() {
+        let e = CommandError::PermissionDenied("/dev/sda: Permission denied".to_string());
+        assert_eq!(e.to_string(), "permission denied: /dev/sda: Permission denied");
+    }
+}
@@ -0,0 +1,339 @@
+extern crate rust_decimal;
+extern crate lazy_static;
+
+use std::str::FromStr;
+use std::collections::BTreeMap;
+
+use super::measurement::{Measurement, Unit, Value};
+
+use lazy_static::lazy_static;
+use rust_decimal::Decimal;
+
+macro_rules! TEMPERATURE_FORMAT { () => { "{:+.1}°C" }; }
+
+pub fn format_amount(mantissa: Decimal) -> String {
+    if mantissa < Decimal::ONE && mantissa != Decimal::ZERO {
+        format!("{:.2}", mantissa)
+    } else if mantissa < Decimal::ONE_HUNDRED && mantissa.fract() != Decimal::ZERO {
+        format!("{:.1}", mantissa)
+    } else {
+        format!("{:.0}", mantissa)
+    }
+}
+
+lazy_static! {
+    static ref BYTE_SUFFIX_MAP: BTreeMap<u64, &'static str> = {
+        let mut map = BTreeMap::new();
+        map.insert(10 * (1 << 10), "K");
+        map.insert(10 * (1 << 20), "M");
+        map.insert(10 * (1 << 30), "G");
+        map.insert(10 * (1 << 40), "T");
+        map.insert(10 * (1 << 50), "P");
+        map
+    };
+}
+fn bearer_scale(bearer: u64, bytes: bool) -> (Decimal, &'static str) {
+    let mut bearer_ceil = &10;
+    for ceil in BYTE_SUFFIX_MAP.keys() {
+        // stop when this ceil would lead to a leading zero or too many digits
+        if bearer < *ceil {
+            break;
+        }
+        bearer_ceil = ceil;
+    }
+
+    let bearer_suffix = match BYTE_SUFFIX_MAP.get(bearer_ceil) {
+        Some(suffix) => suffix,
+        None => if bytes { "B" } else { "" }
+    };
+
+    (Decimal::from(bearer_ceil / 10), bearer_suffix)
+}
+
+pub fn format_two_amounts(a1: u64, a2: u64, separator: &str, bytes: bool) -> String {
+    let (bearer_ceil, bearer_suffix) = bearer_scale(std::cmp::max(a1, a2), bytes);
+
+    format_amount(Decimal::from(a1) / bearer_ceil) + separator +
+        &format_amount(Decimal::from(a2) / bearer_ceil) + bearer_suffix
+}
+
+pub fn format_value(value: &Value) -> String {
+    if value.amount.is_nan() {
+        return "?".to_string();
+    }
+
+    match value.unit {
+        Unit::Bytes => {
+            let (bearer_ceil, bearer_suffix) = bearer_scale(value.amount as u64, true);
+            format_amount(Decimal::from(value.amount as u64) / bearer_ceil) + bearer_suffix
+        },
+        Unit::BytesPerSecond => {
+            let (bearer_ceil, bearer_suffix) = bearer_scale(value.amount as u64, true);
+            format_amount(Decimal::from(value.amount as u64) / bearer_ceil) + bearer_suffix + "/s"
+        },
+        Unit::Percent => format!("{:.0}%", value.amount),
+        Unit::Celsius => format!(TEMPERATURE_FORMAT!(), value.amount),
+        // chip voltages like 1.21V, the mains like 230V
+        Unit::Volts if value.amount.abs() < 100.0 => format!("{:.2}V", value.amount),
+        Unit::Volts => format!("{:.0}V", value.amount.trunc()),
+        Unit::Rpm => format!("{:.0}RPM", value.amount),
+        Unit::Watts => format!("{:.2}W", value.amount),
+        Unit::WattHours => format!("{:.1}Wh", value.amount),
+        Unit::DBm => format!("{}dBm", value.amount),
+        Unit::None => match Decimal::from_str(&value.amount.to_string()) {
+            Ok(amount) => format_amount(amount),
+            Err(_) => value.amount.to_string(),
+        },
+    }
+}
+
+fn format_pair(measurement: &Measurement, separator: &str) -> String {
+    match (measurement.values.first(), measurement.values.get(1)) {
+        // e.g. a counter was reset, there's nothing to scale
+        (Some(a1), Some(a2)) if a1.amount.is_nan() || a2.amount.is_nan() => "?".to_string(),
+        (Some(a1), Some(a2)) if a1.unit == Unit::BytesPerSecond =>
+            format_two_amounts(a1.amount as u64, a2.amount as u64, separator, true) + "/s",
+        (Some(a1), Some(a2)) => format_two_amounts(a1.amount as u64, a2.amount as u64, separator, a1.unit == Unit::Bytes),
+        (Some(a1), None) => format_value(a1),
+        _ => "?".to_string(),
+    }
+}
+
+// used/total and the like
+pub fn format_slash_pair(measurement: &Measurement) -> String {
+    format_pair(measurement, "/")
+}
+
+// rx:tx and the like
+pub fn format_colon_pair(measurement: &Measurement) -> String {
+    format_pair(measurement, ":")
+}
+
+pub fn format_single(measurement: &Measurement) -> String {
+    match measurement.values.first() {
+        Some(value) => format_value(value),
+        None => "?".to_string(),
+    }
+}
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// one block per percentage, a blank for an unknown one
+pub fn format_sparkline<I: Iterator<Item = f64>>(percentages: I) -> String {
+    percentages.map(|percentage| {
+        if percentage.is_nan() {
+            ' '
+        } else {
+            SPARKS[((percentage / 100.0 * SPARKS.len() as f64) as usize).min(SPARKS.len() - 1)]
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // unexpected behaviour, but shoudn't fail at least
+    #[test]
+    fn amount_format_negative() {
+        let formatted = format_amount(Decimal::new(-2368, 2));
+        assert_eq!(formatted, "-23.68");
+    }
+
+    #[test]
+    fn amount_format_zero() {
+        let formatted = format_amount(Decimal::from(0));
+        assert_eq!(formatted, "0");
+    }
+
+    #[test]
+    fn amount_format_tiny() {
+        let formatted = format_amount(Decimal::new(1, 3));
+        assert_eq!(formatted, "0.00");
+    }
+
+    #[test]
+    fn amount_format_almost_one() {
+        let formatted = format_amount(Decimal::new(996, 3));
+        assert_eq!(formatted, "0.99");
+    }
+
+    #[test]
+    fn amount_format_one() {
+        let formatted = format_amount(Decimal::from(1));
+        assert_eq!(formatted, "1");
+    }
+
+    #[test]
+    fn amount_format_almost_ten() {
+        let formatted = format_amount(Decimal::new(975, 2));
+        assert_eq!(formatted, "9.7");
+    }
+
+    #[test]
+    fn amount_format_ten() {
+        let formatted = format_amount(Decimal::from(10));
+        assert_eq!(formatted, "10");
+    }
+
+    #[test]
+    fn amount_format_ten_plus() {
+        let formatted = format_amount(Decimal::new(1000001, 5));
+        assert_eq!(formatted, "10.0");
+    }
+
+    #[test]
+    fn amount_format_99() {
+        let formatted = format_amount(Decimal::new(9995, 2));
+        assert_eq!(formatted, "99.9");
+    }
+
+    #[test]
+    fn amount_format_hundred() {
+        let formatted = format_amount(Decimal::from(100));
+        assert_eq!(formatted, "100");
+    }
+
+    #[test]
+    fn amount_format_alot() {
+        let formatted = format_amount(Decimal::new(123456, 2));
+        assert_eq!(formatted, "1234");
+    }
+
+    #[test]
+    fn amount_format_huge() {
+        let formatted = format_amount(Decimal::new(123749089, 2));
+        assert_eq!(formatted, "1237490");
+    }
+
+    #[test]
+    fn amount_format_near_boundary() {
+        let formatted = format_amount(Decimal::from(1073750016) / Decimal::from(1 << 30));
+        assert_eq!(formatted, "1.0");
+    }
+
+    #[test]
+    fn two_amounts_bytes() {
+        let formatted = format_two_amounts(3, 687, "/", true);
+        assert_eq!(formatted, "3/687B");
+    }
+
+    #[test]
+    fn two_amounts_zero() {
+        let formatted = format_two_amounts(0, 0, ":", true);
+        assert_eq!(formatted, "0:0B");
+    }
+
+    #[test]
+    fn two_amounts_zero_nobytes() {
+        let formatted = format_two_amounts(0, 0, ":", false);
+        assert_eq!(formatted, "0:0");
+    }
+
+    #[test]
+    fn two_amounts_zero_of_more() {
+        let formatted = format_two_amounts(0, 102938, "'", true);
+        assert_eq!(formatted, "0'100K");
+    }
+
+    #[test]
+    fn two_amounts_mega() {
+        let formatted = format_two_amounts(1232899, 2389999, "=", false);
+        assert_eq!(formatted, "1204=2333K");
+    }
+
+    #[test]
+    fn two_amounts_mega_slight_asym() {
+        let formatted = format_two_amounts(1232899, 23899999, "⁚", false);
+        assert_eq!(formatted, "1.1⁚22.7M");
+    }
+
+    #[test]
+    fn two_amounts_mega_very_asym() {
+        let formatted = format_two_amounts(123289, 23899999, r"\", false);
+        assert_eq!(formatted, r"0.11\22.7M");
+    }
+
+    #[test]
+    fn two_amounts_mega_extreme_asym() {
+        let formatted = format_two_amounts(123289, 23899999999, "O", true);
+        assert_eq!(formatted, "0.00O22.2G");
+    }
+
+    #[test]
+    fn two_amounts_first_larger() {
+        let formatted = format_two_amounts(23899999999, 123289, "lol", true);
+        assert_eq!(formatted, "22.2lol0.00G");
+    }
+
+    #[test]
+    fn value_celsius() {
+        let formatted = format_single(&Measurement::new(&[("temperature", 45.04, Unit::Celsius)]));
+        assert_eq!(formatted, "+45.0°C");
+    }
+
+    #[test]
+    fn value_volts() {
+        assert_eq!(format_single(&Measurement::new(&[("voltage", 1.208, Unit::Volts)])), "1.21V");
+        assert_eq!(format_single(&Measurement::new(&[("input", 229.6, Unit::Volts)])), "229V");
+    }
+
+    #[test]
+    fn value_rpm() {
+        let formatted = format_single(&Measurement::new(&[("fan", 1187.0, Unit::Rpm)]));
+        assert_eq!(formatted, "1187RPM");
+    }
+
+    #[test]
+    fn value_bytes() {
+        let formatted = format_single(&Measurement::new(&[("size", 102938.0, Unit::Bytes)]));
+        assert_eq!(formatted, "100K");
+    }
+
+    #[test]
+    fn value_bytes_per_second() {
+        let formatted = format_single(&Measurement::new(&[("read", 102938.0, Unit::BytesPerSecond)]));
+        assert_eq!(formatted, "100K/s");
+    }
+
+    #[test]
+    fn value_nan() {
+        let formatted = format_single(&Measurement::new(&[("level", f64::NAN, Unit::Percent)]));
+        assert_eq!(formatted, "?");
+    }
+
+    #[test]
+    fn value_plain() {
+        let formatted = format_single(&Measurement::new(&[("count", 12.5, Unit::None)]));
+        assert_eq!(formatted, "12.5");
+    }
+
+    #[test]
+    fn pair_bytes() {
+        let formatted = format_slash_pair(&Measurement::new(&[("used", 1232899.0, Unit::Bytes), ("total", 23899999.0, Unit::Bytes)]));
+        assert_eq!(formatted, "1.1/22.7M");
+    }
+
+    #[test]
+    fn pair_bytes_per_second() {
+        let formatted = format_colon_pair(&Measurement::new(&[("rx", 1232899.0, Unit::BytesPerSecond), ("tx", 512.0, Unit::BytesPerSecond)]));
+        assert_eq!(formatted, "1204:0.50K/s");
+    }
+
+    #[test]
+    fn pair_nan() {
+        let formatted = format_colon_pair(&Measurement::new(&[("rx", f64::NAN, Unit::BytesPerSecond), ("tx", 512.0, Unit::BytesPerSecond)]));
+        assert_eq!(formatted, "?");
+    }
+
+    #[test]
+    fn sparkline_levels() {
+        assert_eq!(format_sparkline(vec![0.0, 12.5, 49.0, 99.9, 100.0, -1.0, f64::NAN].into_iter()), "▁▂▄██▁ ");
+    }
+
+    #[test]
+    fn pair_nobytes() {
+        let formatted = format_colon_pair(&Measurement::new(&[("high", 0.0, Unit::None), ("low", 0.0, Unit::None)]));
+        assert_eq!(formatted, "0:0");
+    }
+}
@@ -1,30 +1,54 @@
 extern crate itertools;
+extern crate serde;
+extern crate serde_json;
 
+pub mod cache;
 pub mod commands;
+pub mod config;
+pub mod delta;
+pub mod error;
+pub mod format;
+pub mod measurement;
+pub mod state;
 pub mod utils;
 
+use std::time;
+
 use itertools::free::join;
+use serde::Serialize;
+
+use error::CommandError;
+use measurement::{Measurement, Severity};
 
 const ICON_WIDTH: usize = 1;
 
 pub struct LimonItem {
+    name: &'static str,
+    instance: String,
     icon: char,
     pub bar: Option<u8>,
     pre_spaces: usize,
     post_spaces: usize,
     value: String,
+    measurement: Option<Measurement>,
+    pub error: Option<CommandError>,
 }
 
 macro_rules! format_icon { ($i:expr, $pre_spaces:expr) => { format!("{:>width$}", $i, width = $pre_spaces + ICON_WIDTH) } }
 
 pub fn output_plain(items: Vec<LimonItem>) -> String {
-    let text = join(items.iter().map(|item| format!("{}\t{}\n", format_icon!(item.icon, item.pre_spaces), item.value)), &"");
+    let text = join(items.iter().map(|item| format!("{}\t{}\n", format_icon!(item.icon, item.pre_spaces), item.value)), "");
 
     print!("{}", text);
 
     text
 }
 
+// values and causes may have paths or messages with markup characters
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
 pub fn output_pango(items: Vec<LimonItem>, icon_font_size: u16, text_font: &str, text_font_size: u16, bar: Option<u8>) -> String {
     let mut text = join(items.iter().map(|item| {
         format!(
@@ -33,9 +57,9 @@ pub fn output_pango(items: Vec<LimonItem>, icon_font_size: u16, text_font: &str,
             format_icon!(item.icon, item.pre_spaces),
             text_font,
             text_font_size,
-            item.value,
+            escape_markup(&item.value),
         )
-    }), &"\n");
+    }), "\n");
 
     text.insert_str(0, "<txt>");
     text.push_str("</txt>");
@@ -43,46 +67,291 @@ pub fn output_pango(items: Vec<LimonItem>, icon_font_size: u16, text_font: &str,
         text.push_str(&format!("<bar>{}</bar>", bar));
     }
 
+    // the causes go to the tooltip, the panel only has room for placeholders
+    let errors: Vec<String> = items.iter()
+        .filter_map(|item| item.error.as_ref().map(|error| escape_markup(&format!("{}: {}", item.name, error))))
+        .collect();
+    if !errors.is_empty() {
+        text.push_str(&format!("<tool>{}</tool>", join(errors, "\n")));
+    }
+
+    print!("{}", text);
+
+    text
+}
+
+macro_rules! format_offset { ($spaces:expr, $space_width:expr) => {
+    if $spaces > 0 { format!("%{{O{}}}", $spaces * $space_width) } else { String::new() }
+} }
+
+// a value like "25%" would otherwise run into the next tag
+fn escape_tags(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+// the tag syntax is shared by lemonbar and polybar
+pub fn output_lemonbar(items: Vec<LimonItem>, icon_font: u8, icon_color: Option<&str>, space_width: usize) -> String {
+    let mut text = join(items.iter().map(|item| {
+        let icon = format!("%{{T{}}}{}%{{T-}}", icon_font, item.icon);
+        let icon = match icon_color {
+            Some(color) => format!("%{{F{}}}{}%{{F-}}", color, icon),
+            None => icon,
+        };
+
+        format!(
+            "{}{}{}{}{}",
+            format_offset!(item.pre_spaces, space_width),
+            icon,
+            format_offset!(1, space_width),
+            escape_tags(&item.value),
+            format_offset!(item.post_spaces, space_width),
+        )
+    }), "");
+    text.push('\n');
+
     print!("{}", text);
 
     text
 }
 
-pub fn exec_command(command: &commands::Command, arguments: &[&str]) -> LimonItem {
-    let (icon, result, bar, pre_spaces, post_spaces) = match command {
-        commands::Command::Static(command) => (command.icon, (command.call)(arguments), None, command.pre_spaces, command.post_spaces),
-        commands::Command::Dynamic(command) => {
-            let result = (command.call)(arguments);
-            match result {
-                Some(result) => (result.icon, Some(result.text), result.bar, result.pre_spaces, result.post_spaces),
-                None => (' ', None, None, 0, 0),
+#[derive(Serialize)]
+struct I3barBlock<'a> {
+    name: &'a str,
+    instance: &'a str,
+    full_text: String,
+    short_text: &'a str,
+    separator_block_width: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    urgent: bool,
+}
+
+// starts the infinite array, status lines follow it
+pub fn output_i3bar_header() -> String {
+    let text = "{\"version\":1}\n[\n".to_string();
+
+    print!("{}", text);
+
+    text
+}
+
+pub fn output_i3bar(items: Vec<LimonItem>, space_width: usize) -> String {
+    let blocks: Vec<I3barBlock> = items.iter().map(|item| I3barBlock {
+        name: item.name,
+        instance: &item.instance,
+        full_text: format!("{} {}", format_icon!(item.icon, item.pre_spaces), item.value),
+        short_text: &item.value,
+        separator_block_width: item.post_spaces * space_width,
+        urgent: item.error.is_some(),
+    }).collect();
+
+    let mut text = serde_json::to_string(&blocks).expect("Can't serialize i3bar blocks");
+    text.push_str(",\n");
+
+    print!("{}", text);
+
+    text
+}
+
+#[derive(Serialize)]
+struct WaybarModule {
+    text: String,
+    tooltip: String,
+    class: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percentage: Option<u8>,
+}
+
+// waybar reads the text and the tooltip as pango markup
+pub fn output_waybar(items: Vec<LimonItem>, bar: Option<u8>) -> String {
+    let module = WaybarModule {
+        text: join(items.iter().map(|item| {
+            format!("{} {}", format_icon!(item.icon, item.pre_spaces), escape_markup(&item.value))
+        }), "  "),
+        tooltip: join(items.iter().map(|item| {
+            let description = match commands::find(item.name) {
+                Some(info) => info.description,
+                None => item.name,
+            };
+
+            let value = match &item.error {
+                Some(error) => error.to_string(),
+                None => item.value.clone(),
+            };
+
+            escape_markup(&if item.instance.is_empty() {
+                format!("{}: {}", description, value)
+            } else {
+                format!("{} ({}): {}", description, item.instance, value)
+            })
+        }), "\n"),
+        class: {
+            let mut class = vec!["limon"];
+            let severity = items.iter()
+                .filter_map(|item| item.measurement.as_ref())
+                .map(|measurement| measurement.severity)
+                .fold(Severity::Normal, |a, b| if b > a { b } else { a });
+            match severity {
+                Severity::Warning => class.push("warning"),
+                Severity::Critical => class.push("critical"),
+                Severity::Normal => {},
+            }
+            if items.iter().any(|item| item.error.is_some()) {
+                class.push("error");
             }
+            class
         },
+        percentage: bar,
+    };
+
+    let mut text = serde_json::to_string(&module).expect("Can't serialize the waybar module");
+    text.push('\n');
+
+    print!("{}", text);
+
+    text
+}
+
+#[derive(Serialize)]
+struct JsonItem<'a> {
+    name: &'a str,
+    instance: &'a str,
+    icon: char,
+    text: &'a str,
+    values: serde_json::Map<String, serde_json::Value>,
+    units: serde_json::Map<String, serde_json::Value>,
+    ratio: Option<f64>,
+    severity: Option<Severity>,
+    label: Option<&'a str>,
+    error: Option<&'a CommandError>,
+}
+
+pub fn output_json(items: Vec<LimonItem>) -> String {
+    let json_items: Vec<JsonItem> = items.iter().map(|item| {
+        let values = match &item.measurement {
+            Some(measurement) => &measurement.values[..],
+            None => &[],
+        };
+
+        JsonItem {
+            name: item.name,
+            instance: &item.instance,
+            icon: item.icon,
+            text: &item.value,
+            // NaN and infinities turn into null
+            values: values.iter().map(|value| (value.name.clone(), serde_json::Value::from(value.amount))).collect(),
+            units: values.iter().map(|value| (value.name.clone(), serde_json::json!(value.unit))).collect(),
+            ratio: item.measurement.as_ref().and_then(|measurement| measurement.ratio),
+            severity: item.measurement.as_ref().map(|measurement| measurement.severity),
+            label: item.measurement.as_ref().and_then(|measurement| measurement.label.as_deref()),
+            error: item.error.as_ref(),
+        }
+    }).collect();
+
+    let mut text = serde_json::to_string(&json_items).expect("Can't serialize items");
+    text.push('\n');
+
+    print!("{}", text);
+
+    text
+}
+
+// the genmon bar and the waybar percentage show the level of the dynamic
+// icon command (the battery), wherever it is in the list
+pub fn panel_bar(items: &[LimonItem]) -> Option<u8> {
+    let is_dynamic = |item: &&LimonItem| matches!(commands::find(item.name).map(|info| &info.command), Some(commands::Command::Dynamic(_)));
+
+    items.iter().filter(is_dynamic).find_map(|item| item.bar)
+}
+
+pub fn exec_command(info: &commands::CommandInfo, arguments: &[&str]) -> LimonItem {
+    exec_command_cached(info, arguments, None)
+}
+
+// with a refresh interval the last good measurement is reused until it expires
+pub fn exec_command_cached(info: &commands::CommandInfo, arguments: &[&str], refresh: Option<time::Duration>) -> LimonItem {
+    let measure = match &info.command {
+        commands::Command::Static(command) => command.measure,
+        commands::Command::Dynamic(command) => command.measure,
+    };
+
+    let measurement = match refresh {
+        Some(refresh) => cache::measure(&cache::key(info.name, &arguments.join(":")), refresh, || measure(arguments)),
+        None => measure(arguments),
+    };
+
+    make_item(info, arguments, measurement)
+}
+
+// for commands that didn't get to a result, e.g. timed out
+pub fn error_item(info: &commands::CommandInfo, arguments: &[&str], error: CommandError) -> LimonItem {
+    make_item(info, arguments, Err(error))
+}
+
+fn make_item(info: &commands::CommandInfo, arguments: &[&str], measurement: Result<Measurement, CommandError>) -> LimonItem {
+    let (format, pre_spaces, post_spaces) = match &info.command {
+        commands::Command::Static(command) => (command.format, command.pre_spaces, command.post_spaces),
+        commands::Command::Dynamic(command) => (command.format, command.pre_spaces, command.post_spaces),
+    };
+
+    let icon = match (&info.command, &measurement) {
+        (commands::Command::Static(command), _) => command.icon,
+        (commands::Command::Dynamic(command), Ok(measurement)) => (command.icon)(measurement),
+        (commands::Command::Dynamic(_), Err(_)) => ' ',
+    };
+
+    let value = match &measurement {
+        Ok(Measurement { message: Some(message), .. }) => message.clone(),
+        Ok(measurement) => format(measurement, arguments),
+        Err(error) => error.placeholder().to_string(),
+    };
+    let value = match &measurement {
+        Ok(Measurement { label: Some(label), .. }) => format!("{} {}", label, value),
+        _ => value,
+    };
+    let (measurement, error) = match measurement {
+        Ok(measurement) => (Some(measurement), None),
+        Err(error) => (None, Some(error)),
     };
 
     LimonItem {
-        icon: icon,
-        value: match result {
-            Some(v) => v,
-            None => "#ERROR#".to_string(),
-        },
-        bar: bar,
-        pre_spaces: pre_spaces,
-        post_spaces: post_spaces,
+        name: info.name,
+        instance: arguments.join(":"),
+        icon,
+        value,
+        bar: measurement.as_ref().and_then(Measurement::bar),
+        measurement,
+        error,
+        pre_spaces,
+        post_spaces,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use measurement::Unit;
 
     fn _two_test_lines() -> Vec<LimonItem> {
         vec!(
-            LimonItem { icon: 'a', value: "tist".to_string(), bar: None, pre_spaces: 0, post_spaces: 8 },
-            LimonItem { icon: 'b', value: "zizd".to_string(), bar: None, pre_spaces: 1, post_spaces: 4 },
+            LimonItem {
+                name: "a", instance: "".to_string(), icon: 'a', value: "tist".to_string(), bar: None, pre_spaces: 0, post_spaces: 8,
+                measurement: Some(Measurement::new(&[("used", 1.5, Unit::Bytes), ("total", 3.0, Unit::Bytes)]).with_ratio(0.5)), error: None,
+            },
+            LimonItem {
+                name: "b", instance: "eth0".to_string(), icon: 'b', value: "zizd".to_string(), bar: None, pre_spaces: 1, post_spaces: 4,
+                measurement: Some(Measurement::new(&[("level", f64::NAN, Unit::DBm)]).with_severity(Severity::Warning)), error: None,
+            },
         )
     }
 
+    fn _error_line() -> LimonItem {
+        let error = CommandError::PermissionDenied("/dev/sda: Permission denied".to_string());
+        LimonItem {
+            name: "ata_hddtemp", instance: "/dev/sda".to_string(), icon: 'h', value: error.placeholder().to_string(), bar: None, pre_spaces: 0, post_spaces: 3,
+            measurement: None, error: Some(error),
+        }
+    }
+
     #[test]
     fn output_plain_row_count() {
         let text = output_plain(_two_test_lines());
@@ -105,6 +374,216 @@ mod tests {
         assert!(lines.next_back().unwrap().ends_with("</span></txt><bar>23</bar>"));
     }
 
+    #[test]
+    fn output_pango_error_tooltip() {
+        let mut items = _two_test_lines();
+        items.push(_error_line());
+        let text = output_pango(items, 12, "Comic Sans", 11, None);
+        assert!(text.contains(">#EPERM#</span></txt>"));
+        assert!(text.ends_with("<tool>ata_hddtemp: permission denied: /dev/sda: Permission denied</tool>"));
+    }
+
+    #[test]
+    fn output_pango_escapes_markup() {
+        let info = commands::find("fs_free").unwrap();
+        let item = error_item(info, &["/mnt/<a&b>"], CommandError::NotAvailable("/mnt/<a&b>: No such file or directory".to_string()));
+        let mut items = _two_test_lines();
+        items[0].value = "1<2 & 3>2".to_string();
+        items.push(item);
+        let text = output_pango(items, 12, "Comic Sans", 11, None);
+        assert!(text.contains(">1&lt;2 &amp; 3&gt;2</span>"));
+        assert!(text.ends_with("<tool>fs_free: not available: /mnt/&lt;a&amp;b&gt;: No such file or directory</tool>"));
+    }
+
+    #[test]
+    fn output_pango_no_tooltip_without_errors() {
+        let text = output_pango(_two_test_lines(), 12, "Comic Sans", 11, None);
+        assert!(!text.contains("<tool>"));
+    }
+
+    #[test]
+    fn output_i3bar_header_starts_array() {
+        let text = output_i3bar_header();
+        let mut lines = text.lines();
+        let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header["version"], 1);
+        assert_eq!(lines.next(), Some("["));
+    }
+
+    #[test]
+    fn output_i3bar_blocks() {
+        let text = output_i3bar(_two_test_lines(), 5);
+        assert!(text.ends_with("],\n"));
+
+        let blocks: serde_json::Value = serde_json::from_str(text.trim_end().trim_end_matches(',')).unwrap();
+        assert_eq!(blocks.as_array().unwrap().len(), 2);
+        assert_eq!(blocks[0]["name"], "a");
+        assert_eq!(blocks[0]["full_text"], "a tist");
+        assert_eq!(blocks[0]["short_text"], "tist");
+        assert_eq!(blocks[0]["separator_block_width"], 40);
+        assert_eq!(blocks[1]["instance"], "eth0");
+        assert_eq!(blocks[1]["full_text"], " b zizd");
+        assert_eq!(blocks[1]["separator_block_width"], 20);
+    }
+
+    #[test]
+    fn output_i3bar_urgent() {
+        let mut items = _two_test_lines();
+        items.push(_error_line());
+        let text = output_i3bar(items, 5);
+        let blocks: serde_json::Value = serde_json::from_str(text.trim_end().trim_end_matches(',')).unwrap();
+        assert!(blocks[0].get("urgent").is_none());
+        assert_eq!(blocks[2]["urgent"], true);
+        assert_eq!(blocks[2]["short_text"], "#EPERM#");
+    }
+
+    #[test]
+    fn output_waybar_one_line() {
+        let text = output_waybar(_two_test_lines(), None);
+        assert_eq!(text.lines().count(), 1);
+
+        let module: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(module["text"], "a tist   b zizd");
+        assert_eq!(module["tooltip"], "a: tist\nb (eth0): zizd");
+        assert_eq!(module["class"][0], "limon");
+        assert_eq!(module["class"][1], "warning");
+        assert!(module.get("percentage").is_none());
+    }
+
+    #[test]
+    fn output_waybar_with_bar() {
+        let text = output_waybar(_two_test_lines(), Some(23));
+        let module: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(module["percentage"], 23);
+    }
+
+    #[test]
+    fn output_waybar_tooltip_description() {
+        let items = vec!(
+            LimonItem { name: "mem", instance: "".to_string(), icon: 'm', value: "1/2G".to_string(), measurement: None, error: None, bar: None, pre_spaces: 0, post_spaces: 3 },
+        );
+        let text = output_waybar(items, None);
+        let module: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(module["tooltip"], "used/total RAM: 1/2G");
+    }
+
+    #[test]
+    fn output_waybar_error() {
+        let text = output_waybar(vec!(_error_line()), None);
+        let module: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(module["text"], "h #EPERM#");
+        assert_eq!(module["tooltip"], "disk temperature from S.M.A.R.T. (/dev/sda): permission denied: /dev/sda: Permission denied");
+        assert_eq!(module["class"][1], "error");
+    }
+
+    #[test]
+    fn output_waybar_escapes_markup() {
+        let info = commands::find("fs_free").unwrap();
+        let item = error_item(info, &["/mnt/<a&b>"], CommandError::NotAvailable("/mnt/<a&b>: No such file or directory".to_string()));
+        let mut items = _two_test_lines();
+        items[0].value = "1<2".to_string();
+        items.push(item);
+        let text = output_waybar(items, None);
+        let module: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert!(module["text"].as_str().unwrap().contains("1&lt;2"));
+        assert!(module["tooltip"].as_str().unwrap().ends_with("(/mnt/&lt;a&amp;b&gt;): not available: /mnt/&lt;a&amp;b&gt;: No such file or directory"));
+    }
+
+    #[test]
+    fn output_lemonbar_tags() {
+        let text = output_lemonbar(_two_test_lines(), 2, None, 3);
+        assert_eq!(text, "%{T2}a%{T-}%{O3}tist%{O24}%{O3}%{T2}b%{T-}%{O3}zizd%{O12}\n");
+    }
+
+    #[test]
+    fn output_lemonbar_escapes_percent() {
+        let mut items = _two_test_lines();
+        items[1].value = "·25%".to_string();
+        let text = output_lemonbar(items, 2, None, 3);
+        assert!(text.ends_with("%{O3}·25%%%{O12}\n"));
+    }
+
+    #[test]
+    fn output_lemonbar_icon_color() {
+        let text = output_lemonbar(_two_test_lines(), 1, Some("#ffb52a"), 3);
+        assert!(text.starts_with("%{F#ffb52a}%{T1}a%{T-}%{F-}%{O3}tist"));
+        assert_eq!(text.lines().count(), 1);
+        assert!(!text.contains('\t'));
+    }
+
+    #[test]
+    fn output_json_values() {
+        let text = output_json(_two_test_lines());
+        assert_eq!(text.lines().count(), 1);
+
+        let items: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(items[0]["name"], "a");
+        assert_eq!(items[0]["icon"], "a");
+        assert_eq!(items[0]["text"], "tist");
+        assert_eq!(items[0]["values"]["used"], 1.5);
+        assert_eq!(items[0]["values"]["total"], 3.0);
+        assert_eq!(items[0]["units"]["used"], "bytes");
+        assert_eq!(items[0]["ratio"], 0.5);
+        assert_eq!(items[0]["severity"], "normal");
+        assert_eq!(items[1]["severity"], "warning");
+        assert_eq!(items[1]["instance"], "eth0");
+        assert!(items[1]["values"]["level"].is_null());
+    }
+
+    #[test]
+    fn output_json_error() {
+        let mut items = _two_test_lines();
+        items.push(_error_line());
+        let text = output_json(items);
+        let items: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert!(items[0]["error"].is_null());
+        assert_eq!(items[2]["error"]["kind"], "permission_denied");
+        assert_eq!(items[2]["error"]["message"], "/dev/sda: Permission denied");
+        assert!(items[2]["severity"].is_null());
+    }
+
+    #[test]
+    fn exec_command_missing_argument() {
+        let item = exec_command(commands::find("fs_free").unwrap(), &[]);
+        assert_eq!(item.value, "#PARSE#");
+        assert_eq!(item.error, Some(CommandError::Parse("missing mountpoint argument".to_string())));
+    }
+
+    #[test]
+    fn label_before_value() {
+        let info = commands::find("network_speed").unwrap();
+        let measurement = Measurement::new(&[("rx", 1024.0, Unit::BytesPerSecond), ("tx", 0.0, Unit::BytesPerSecond)]).with_label("wlan0");
+        let item = make_item(info, &["auto"], Ok(measurement));
+        assert_eq!(item.value, "wlan0 1024:0B/s");
+        assert_eq!(item.instance, "auto");
+    }
+
+    #[test]
+    fn panel_bar_prefers_battery() {
+        let battery = make_item(commands::find("battery").unwrap(), &[], Ok(Measurement::new(&[]).with_ratio(0.8)));
+        let mem = make_item(commands::find("mem").unwrap(), &[], Ok(Measurement::new(&[]).with_ratio(0.12)));
+        assert_eq!(panel_bar(&[battery, mem]), Some(80));
+    }
+
+    #[test]
+    fn panel_bar_without_battery() {
+        let mem = make_item(commands::find("mem").unwrap(), &[], Ok(Measurement::new(&[]).with_ratio(0.12)));
+        let fs_free = make_item(commands::find("fs_free").unwrap(), &["/"], Ok(Measurement::new(&[]).with_ratio(0.5)));
+        assert_eq!(panel_bar(&[mem, fs_free]), None);
+    }
+
+    #[test]
+    fn error_item_keeps_static_icon() {
+        let info = commands::find("ups_voltage").unwrap();
+        let item = error_item(info, &["nutdev"], CommandError::Transient("timed out after 5s".to_string()));
+        match &info.command {
+            commands::Command::Static(command) => assert_eq!(item.icon, command.icon),
+            commands::Command::Dynamic(_) => panic!("ups_voltage has a static icon"),
+        }
+        assert_eq!(item.instance, "nutdev");
+        assert_eq!(item.value, "#AGAIN#");
+    }
+
     #[test]
     fn output_plain_no_markup() {
         let text = output_plain(_two_test_lines());
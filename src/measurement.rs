@@ -0,0 +1,172 @@
+extern crate serde;
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    None,
+    Bytes,
+    #[serde(rename = "bytes/s")]
+    BytesPerSecond,
+    Percent,
+    Celsius,
+    Volts,
+    Rpm,
+    Watts,
+    WattHours,
+    DBm,
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    // for ratios where more is worse, like used memory
+    pub fn from_ratio(ratio: f64, warning: f64, critical: f64) -> Severity {
+        if ratio >= critical {
+            Severity::Critical
+        } else if ratio >= warning {
+            Severity::Warning
+        } else {
+            Severity::Normal
+        }
+    }
+}
+
+// JSON has no NaN, e.g. a reset counter is written as null and read back
+mod nan_as_null {
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+
+    pub fn serialize<S: Serializer>(amount: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if amount.is_nan() {
+            serializer.serialize_none()
+        } else {
+            amount.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NAN))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Value {
+    pub name: String,
+    #[serde(with = "nan_as_null")]
+    pub amount: f64,
+    pub unit: Unit,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Measurement {
+    pub values: Vec<Value>,
+    // 0..1, drives bars and percentages
+    pub ratio: Option<f64>,
+    pub severity: Severity,
+    // shown instead of the values when there's nothing to measure
+    pub message: Option<String>,
+    // what was actually measured, e.g. the interface behind "auto"
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl Measurement {
+    pub fn new(values: &[(&str, f64, Unit)]) -> Measurement {
+        Measurement {
+            values: values.iter().map(|(name, amount, unit)| Value {
+                name: name.to_string(),
+                amount: *amount,
+                unit: *unit,
+            }).collect(),
+            ratio: None,
+            severity: Severity::Normal,
+            message: None,
+            label: None,
+        }
+    }
+
+    pub fn message(message: &str) -> Measurement {
+        Measurement {
+            values: vec![],
+            ratio: None,
+            severity: Severity::Normal,
+            message: Some(message.to_string()),
+            label: None,
+        }
+    }
+
+    pub fn with_ratio(mut self, ratio: f64) -> Measurement {
+        self.ratio = Some(ratio);
+        self
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Measurement {
+        self.severity = severity;
+        self
+    }
+
+    pub fn with_label(mut self, label: &str) -> Measurement {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    pub fn push(&mut self, name: &str, amount: f64, unit: Unit) {
+        self.values.push(Value {
+            name: name.to_string(),
+            amount,
+            unit,
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.values.iter().find(|value| value.name == name).map(|value| value.amount)
+    }
+
+    // the bar level in percents
+    pub fn bar(&self) -> Option<u8> {
+        self.ratio.map(|ratio| (ratio * 100.0).clamp(0.0, 100.0) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severity_below_warning() {
+        assert_eq!(Severity::from_ratio(0.5, 0.8, 0.9), Severity::Normal);
+    }
+
+    #[test]
+    fn severity_warning() {
+        assert_eq!(Severity::from_ratio(0.8, 0.8, 0.9), Severity::Warning);
+    }
+
+    #[test]
+    fn severity_critical() {
+        assert_eq!(Severity::from_ratio(0.95, 0.8, 0.9), Severity::Critical);
+    }
+
+    #[test]
+    fn measurement_get() {
+        let measurement = Measurement::new(&[("used", 1.0, Unit::Bytes), ("total", 2.0, Unit::Bytes)]);
+        assert_eq!(measurement.get("total"), Some(2.0));
+        assert_eq!(measurement.get("free"), None);
+    }
+
+    #[test]
+    fn measurement_bar() {
+        assert_eq!(Measurement::new(&[]).with_ratio(0.234).bar(), Some(23));
+        // truncated, the battery icon doesn't go up before the level does
+        assert_eq!(Measurement::new(&[]).with_ratio(0.796).bar(), Some(79));
+        assert_eq!(Measurement::new(&[]).with_ratio(1.5).bar(), Some(100));
+        assert_eq!(Measurement::new(&[]).bar(), None);
+    }
+}
@@ -0,0 +1,220 @@
+extern crate lazy_static;
+extern crate libc;
+extern crate serde;
+extern crate serde_json;
+
+use std::env;
+use std::fs;
+use std::path;
+use std::io;
+use std::io::Write;
+use std::process;
+use std::collections::HashMap;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::io::AsRawFd;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::{Serialize, Deserialize};
+
+const STATE_DIR: &str = "limon";
+const DEFAULT_NAMESPACE: &str = "default";
+const VERSION: u32 = 1;
+
+enum Store {
+    // $XDG_RUNTIME_DIR/limon/<namespace>/, one file per state
+    Files(path::PathBuf),
+    // the resident mode, the states don't outlive the process then
+    Memory(HashMap<String, String>),
+}
+
+lazy_static! {
+    static ref STORE: Mutex<Store> = Mutex::new(Store::Files(namespace_dir(DEFAULT_NAMESPACE)));
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateFile {
+    version: u32,
+    state: String,
+}
+
+fn namespace_dir(namespace: &str) -> path::PathBuf {
+    // prefer /run, but /tmp is fine too
+    let mut dir = match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => path::PathBuf::from(dir),
+        Err(_) => env::temp_dir(),
+    };
+    dir.push(STATE_DIR);
+    dir.push(namespace);
+    dir
+}
+
+pub fn keep_in_memory() {
+    let mut store = STORE.lock().unwrap();
+    if let Store::Files(_) = *store {
+        *store = Store::Memory(HashMap::new());
+    }
+}
+
+// command/argument, so the same command with other arguments doesn't mix
+pub fn key(command: &str, argument: &str) -> String {
+    if argument.is_empty() {
+        command.to_string()
+    } else {
+        command.to_owned() + "/" + argument
+    }
+}
+
+// limonbin instances with different namespaces don't see each other's states
+pub fn set_namespace(namespace: &str) -> Result<(), String> {
+    if namespace.is_empty() || !namespace.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("bad namespace \"{}\", only letters, digits, - and _ are allowed", namespace));
+    }
+
+    if let Store::Files(dir) = &mut *STORE.lock().unwrap() {
+        *dir = namespace_dir(namespace);
+    }
+
+    Ok(())
+}
+
+// arguments like /dev/sda end up in the names
+fn file_name(name: &str) -> String {
+    name.replace('%', "%25").replace('/', "%2F")
+}
+
+fn decode(contents: &str) -> Option<String> {
+    match serde_json::from_str::<StateFile>(contents) {
+        Ok(file) if file.version == VERSION => Some(file.state),
+        // written by another version, start over
+        _ => None,
+    }
+}
+
+fn encode(state: &str) -> String {
+    serde_json::to_string(&StateFile { version: VERSION, state: state.to_string() }).expect("Can't serialize a state")
+}
+
+// one lock per state, held until the file is closed; it excludes the other
+// threads too, flock is per open file
+fn lock(dir: &path::Path, name: &str) -> io::Result<fs::File> {
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+
+    let lock_path = dir.join(format!(".{}.lock", file_name(name)));
+    let lock_file = fs::OpenOptions::new().create(true).truncate(false).write(true).open(lock_path)?;
+    if unsafe { libc::flock(lock_file.as_raw_fd(), libc::LOCK_EX) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(lock_file)
+}
+
+fn load_file(dir: &path::Path, name: &str) -> Option<String> {
+    decode(&fs::read_to_string(dir.join(file_name(name))).ok()?)
+}
+
+// readers never see a half-written file, the rename replaces it at once
+fn save_file(dir: &path::Path, name: &str, state: &str) -> io::Result<()> {
+    let file_path = dir.join(file_name(name));
+    let temp_path = dir.join(format!(".{}.{}", file_name(name), process::id()));
+
+    let mut temp_file = fs::File::create(&temp_path)?;
+    temp_file.write_all(encode(state).as_bytes())?;
+    drop(temp_file);
+
+    fs::rename(&temp_path, &file_path)
+}
+
+// STORE is only held to pick the store, a command waiting for a file lock
+// doesn't stop the others
+pub fn load(name: &str) -> Option<String> {
+    let dir = match &*STORE.lock().unwrap() {
+        Store::Memory(states) => return states.get(name).cloned(),
+        Store::Files(dir) => dir.clone(),
+    };
+
+    load_file(&dir, name)
+}
+
+pub fn save(name: &str, state: &str) -> io::Result<()> {
+    let dir = match &mut *STORE.lock().unwrap() {
+        Store::Memory(states) => {
+            states.insert(name.to_string(), state.to_string());
+            return Ok(());
+        },
+        Store::Files(dir) => dir.clone(),
+    };
+
+    let _lock = lock(&dir, name)?;
+    save_file(&dir, name, state)
+}
+
+// saves the new state and returns the previous one
+pub fn persist(name: &str, state: &str) -> io::Result<String> {
+    let dir = match &mut *STORE.lock().unwrap() {
+        // nothing bad if there's no state yet, just return an empty one
+        Store::Memory(states) => return Ok(states.insert(name.to_string(), state.to_string()).unwrap_or_default()),
+        Store::Files(dir) => dir.clone(),
+    };
+
+    // another instance in the same namespace waits for both steps
+    let _lock = lock(&dir, name)?;
+    let prev_state = load_file(&dir, name).unwrap_or_default();
+    save_file(&dir, name, state)?;
+    Ok(prev_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::utils::TestDir;
+
+    #[test]
+    fn key_with_argument() {
+        assert_eq!(key("network_speed", "wlan0"), "network_speed/wlan0");
+        assert_eq!(key("loadavg", ""), "loadavg");
+    }
+
+    #[test]
+    fn file_name_escapes_slashes() {
+        assert_eq!(file_name("ata_hddtemp/dev/sda"), "ata_hddtemp%2Fdev%2Fsda");
+        assert_eq!(file_name("a%2Fb"), "a%252Fb");
+    }
+
+    #[test]
+    fn encoded_roundtrip() {
+        assert_eq!(decode(&encode("1 2 3")), Some("1 2 3".to_string()));
+    }
+
+    #[test]
+    fn other_version() {
+        assert_eq!(decode("{\"version\":0,\"state\":\"1 2\"}"), None);
+    }
+
+    #[test]
+    fn unversioned() {
+        assert_eq!(decode("1 2"), None);
+    }
+
+    #[test]
+    fn bad_namespace() {
+        assert!(set_namespace("../panel").is_err());
+        assert!(set_namespace("").is_err());
+    }
+
+    #[test]
+    fn files_roundtrip() {
+        let dir = TestDir::new("roundtrip");
+        let _lock = lock(&dir, "cpu/cpu0").unwrap();
+
+        assert_eq!(load_file(&dir, "cpu/cpu0"), None);
+        save_file(&dir, "cpu/cpu0", "1 2").unwrap();
+        save_file(&dir, "cpu/cpu0", "3 4").unwrap();
+        assert_eq!(load_file(&dir, "cpu/cpu0"), Some("3 4".to_string()));
+
+        // no temp files left, only the state and its lock
+        let mut names: Vec<String> = dir.read_dir().unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        assert_eq!(names, vec![".cpu%2Fcpu0.lock", "cpu%2Fcpu0"]);
+    }
+}
@@ -1,5 +1,53 @@
-pub fn trim_trailing_newline(s: &mut String) -> () {
+extern crate libc;
+
+pub fn trim_trailing_newline(s: &mut String) {
     if s.ends_with('\n') {
         s.pop();
     }
 }
+
+// nanoseconds since boot, not affected by clock changes, the same for every
+// process and, unlike CLOCK_MONOTONIC, going on during suspend
+pub fn boottime_ns() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts);
+    }
+
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+// a scratch directory for a test, removed on drop, so a failed assert
+// doesn't leave it behind
+#[cfg(test)]
+pub struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(test: &str) -> TestDir {
+        TestDir(std::env::temp_dir().join(format!("limon-test-{}-{}", std::process::id(), test)))
+    }
+
+    // creates the parent directories too, e.g. "card0/device/gpu_busy_percent"
+    pub fn write(&self, file: &str, contents: &str) {
+        let path = self.0.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}